@@ -1,16 +1,84 @@
+use std::{collections::BTreeMap, error::Error};
+
+/// Where Santa went while following the instructions, see [`summarise`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub final_floor: isize,
+    pub highest_floor: isize,
+    pub lowest_floor: isize,
+    /// The positions, counting from 1, of the instructions taking Santa
+    /// into the basement.
+    pub basement_entered: Vec<usize>,
+    /// The positions, counting from 1, of the instructions taking Santa
+    /// out of the basement.
+    pub basement_left: Vec<usize>,
+    /// How many instructions are followed from each floor.
+    pub time_on_floor: BTreeMap<isize, usize>,
+}
+
+/// Follows the instructions once, keeping track of everywhere Santa goes.
+pub fn summarise(input: &str) -> Summary {
+    input
+        .chars()
+        .enumerate()
+        .filter(|(_, brace)| matches!(brace, '(' | ')'))
+        .fold(Summary::default(), |mut summary, (position, brace)| {
+            let floor = summary.final_floor;
+            *summary.time_on_floor.entry(floor).or_default() += 1;
+            let next = move_floor(floor, brace);
+            if floor == 0 && next == -1 {
+                summary.basement_entered.push(position + 1);
+            } else if floor == -1 && next == 0 {
+                summary.basement_left.push(position + 1);
+            }
+            summary.final_floor = next;
+            summary.highest_floor = summary.highest_floor.max(next);
+            summary.lowest_floor = summary.lowest_floor.min(next);
+            summary
+        })
+}
+
 pub fn part_one(input: &str) -> isize {
     input.chars().fold(0, move_floor)
 }
 
-pub fn part_two(input: &str) -> isize {
+pub fn part_two(input: &str) -> Option<usize> {
+    basement_position(input, Mode::Lenient).unwrap()
+}
+
+/// How strictly [`basement_position`] treats its input.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    /// Ignores anything other than `(` and `)`.
+    #[default]
+    Lenient,
+    /// Rejects anything other than `(` and `)`, apart from a final line
+    /// ending.
+    Strict,
+}
+
+/// The position, counting from 1, of the instruction first taking Santa into
+/// the basement, or `None` if Santa never gets there.
+pub fn basement_position(input: &str, mode: Mode) -> Result<Option<usize>, Box<dyn Error>> {
     let mut floor = 0;
-    for (position, brace) in input.chars().enumerate() {
+    let mut basement = None;
+    let instructions = match mode {
+        Mode::Lenient => input,
+        Mode::Strict => input.strip_suffix('\n').unwrap_or(input),
+    };
+    for (position, brace) in instructions.chars().enumerate() {
+        if mode == Mode::Strict && !matches!(brace, '(' | ')') {
+            return Err(format!("unknown character {brace:?} at position {}", position + 1).into());
+        }
         floor = move_floor(floor, brace);
-        if floor == -1 {
-            return position as isize + 1;
+        if floor == -1 && basement.is_none() {
+            basement = Some(position + 1);
+            if mode == Mode::Lenient {
+                break;
+            }
         }
     }
-    0
+    Ok(basement)
 }
 
 fn move_floor(floor: isize, brace: char) -> isize {
@@ -23,7 +91,9 @@ fn move_floor(floor: isize, brace: char) -> isize {
 
 #[cfg(test)]
 mod test {
-    use super::{part_one, part_two};
+    use std::collections::BTreeMap;
+
+    use super::{basement_position, part_one, part_two, summarise, Mode, Summary};
 
     #[test]
     fn example1() {
@@ -57,20 +127,76 @@ mod test {
     fn example1_part2() {
         let input = ")";
         let result = part_two(input);
-        assert_eq!(result, 1);
+        assert_eq!(result, Some(1));
     }
 
     #[test]
     fn example2_part2() {
         let input = "()())";
         let result = part_two(input);
-        assert_eq!(result, 5);
+        assert_eq!(result, Some(5));
     }
 
     #[test]
     fn test_part2() {
         let input = include_str!("../resources/day1_part1");
         let result = part_two(input);
-        assert_eq!(result, 1797);
+        assert_eq!(result, Some(1797));
+    }
+
+    #[test]
+    fn summarise_example() {
+        let input = "())()))((((";
+        let result = summarise(input);
+        assert_eq!(
+            result,
+            Summary {
+                final_floor: 1,
+                highest_floor: 1,
+                lowest_floor: -3,
+                basement_entered: vec![3, 5],
+                basement_left: vec![4, 10],
+                time_on_floor: BTreeMap::from([(-3, 1), (-2, 2), (-1, 3), (0, 4), (1, 1)]),
+            }
+        );
+    }
+
+    #[test]
+    fn summarise_ignores_other_characters() {
+        let input = "(x)\n)";
+        let result = summarise(input);
+        assert_eq!(result.basement_entered, vec![5]);
+        assert_eq!(result.time_on_floor, BTreeMap::from([(0, 2), (1, 1)]));
+    }
+
+    #[test]
+    fn summarise_agrees_with_parts() {
+        let input = include_str!("../resources/day1_part1");
+        let result = summarise(input);
+        assert_eq!(result.final_floor, part_one(input));
+        assert_eq!(result.basement_entered.first().copied(), part_two(input));
+        assert_eq!(
+            result.time_on_floor.values().sum::<usize>(),
+            input.chars().filter(|c| matches!(c, '(' | ')')).count()
+        );
+    }
+
+    #[test]
+    fn part_two_never_in_basement() {
+        assert_eq!(part_two("(()"), None);
+        assert_eq!(part_two(""), None);
+    }
+
+    #[test]
+    fn strict_basement_position() {
+        let input = include_str!("../resources/day1_part1");
+        let result = basement_position(input, Mode::Strict).unwrap();
+        assert_eq!(result, Some(1797));
+        assert_eq!(basement_position("(()", Mode::Strict).unwrap(), None);
+        let error = basement_position("())x", Mode::Strict).unwrap_err();
+        assert_eq!(error.to_string(), "unknown character 'x' at position 4");
+        let error = basement_position("(\n)", Mode::Strict).unwrap_err();
+        assert_eq!(error.to_string(), "unknown character '\\n' at position 2");
+        assert_eq!(basement_position("())x", Mode::Lenient).unwrap(), Some(3));
     }
 }
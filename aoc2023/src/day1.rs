@@ -1,65 +1,382 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
+    ops::Range,
+};
 
 pub fn part_one(input: &str) -> usize {
-    input
-        .lines()
-        .map(|line| {
-            let mut digits = line.chars().filter_map(|ch| {
-                ch.is_ascii_digit()
-                    .then(|| ch.to_digit(10).unwrap() as usize)
-            });
-            let first = digits.next().unwrap_or(0);
-            let last = digits.last().unwrap_or(first);
-            (first * 10) + last
-        })
-        .sum()
+    Vocabulary::digits().calibration_sum(input)
 }
 
-const UNKNOWN: usize = 10;
 const NUMBER_STRINGS: [&str; 10] = [
     "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
 ];
 
+const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
 pub fn part_two(input: &str) -> usize {
-    let numbers_map = NUMBER_STRINGS
-        .iter()
-        .enumerate()
-        .map(|(index, &num_str)| (num_str.to_owned(), index))
-        .collect();
-    input
-        .lines()
-        .map(|line| {
-            let (first, last) = find_numbers(line, &NUMBER_STRINGS, &numbers_map);
-            (first * 10) + last
+    Vocabulary::english().calibration_sum(input)
+}
+
+/// The words that stand for numbers in a calibration document.
+///
+/// ```
+/// use aoc2023::day1::Vocabulary;
+///
+/// let german = Vocabulary::digits().with_words([("eins", 1), ("zwei", 2), ("drei", 3)]);
+/// assert_eq!(german.calibration_sum("xeinsydreiz\nzwei"), 35);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Vocabulary {
+    words: Vec<(String, usize)>,
+}
+
+impl Vocabulary {
+    /// A vocabulary with no words.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The digits `0` to `9`, the vocabulary of part one.
+    pub fn digits() -> Self {
+        Self::new().with_words(DIGITS.iter().copied().zip(0..))
+    }
+
+    /// The digits along with the English words `zero` to `nine`, the
+    /// vocabulary of part two.
+    pub fn english() -> Self {
+        Self::digits().with_words(NUMBER_STRINGS.iter().copied().zip(0..))
+    }
+
+    /// Adds `word`, standing for `value`.
+    pub fn with_word(mut self, word: &str, value: usize) -> Self {
+        self.words.push((word.to_owned(), value));
+        self
+    }
+
+    /// Adds every word in `words`, each paired with the value it stands for.
+    pub fn with_words<'a>(self, words: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        words.into_iter().fold(self, |vocabulary, (word, value)| {
+            vocabulary.with_word(word, value)
+        })
+    }
+
+    /// Sums the calibration value of every line in `input`. The calibration
+    /// value joins the first digit of the first number in a line with the last
+    /// digit of the last number, so with words like `eleven` a line holding
+    /// only that word is worth `11`. Lines without any number are worth `0`.
+    pub fn calibration_sum(&self, input: &str) -> usize {
+        self.lenient_calibration_sum(input).sum
+    }
+
+    /// Like [`Vocabulary::calibration_sum`], but fails on the first line
+    /// without any number.
+    ///
+    /// ```
+    /// use aoc2023::day1::Vocabulary;
+    ///
+    /// let error = Vocabulary::digits().strict_calibration_sum("1a2\nabc").unwrap_err();
+    /// assert_eq!(error.to_string(), "line 2 has no digits: abc");
+    /// ```
+    pub fn strict_calibration_sum(&self, input: &str) -> Result<usize, Box<dyn Error>> {
+        let matcher = self.matcher();
+        input.lines().enumerate().try_fold(0, |sum, (index, line)| {
+            let (first, last) = find_numbers(line, &matcher)
+                .ok_or_else(|| format!("line {} has no digits: {line}", index + 1))?;
+            Ok(sum + calibration_value(first, last))
         })
-        .sum()
-}
-
-fn find_numbers(
-    line: &str,
-    numbers_as_str: &[&str],
-    number_map: &HashMap<String, usize>,
-) -> (usize, usize) {
-    let mut number_positions = vec![UNKNOWN; line.len()];
-    numbers_as_str
-        .iter()
-        .enumerate()
-        .for_each(|(position, &num_str)| {
-            line.match_indices(num_str).for_each(|(index, _)| {
-                number_positions[index] = number_map.get(num_str).copied().unwrap_or(UNKNOWN)
+    }
+
+    /// Like [`Vocabulary::calibration_sum`], but also lists every line without
+    /// any number.
+    pub fn lenient_calibration_sum<'a>(&self, input: &'a str) -> Calibration<'a> {
+        let matcher = self.matcher();
+        input
+            .lines()
+            .enumerate()
+            .fold(Calibration::default(), |mut calibration, (index, line)| {
+                match find_numbers(line, &matcher) {
+                    Some((first, last)) => calibration.sum += calibration_value(first, last),
+                    None => calibration.skipped.push((index + 1, line)),
+                }
+                calibration
+            })
+    }
+
+    /// Lists, for every line in `input`, the numbers picked as its first and
+    /// last, to see where a calibration value comes from.
+    ///
+    /// ```
+    /// use aoc2023::day1::Vocabulary;
+    ///
+    /// let trace = Vocabulary::english().trace("eightwo");
+    /// assert_eq!(trace[0].to_string(), "eightwo: eight at 0..5, two at 4..7 = 82");
+    /// ```
+    pub fn trace<'a>(&self, input: &'a str) -> Vec<LineTrace<'a>> {
+        let matcher = self.matcher();
+        input
+            .lines()
+            .map(|line| {
+                let token = |found: Match| Token {
+                    span: found.start()..found.end,
+                    word: &line[found.start()..found.end],
+                    value: found.value,
+                };
+                let first = matcher.first(line);
+                LineTrace {
+                    line,
+                    first: first.map(token),
+                    last: matcher.last(line).or(first).map(token),
+                }
+            })
+            .collect()
+    }
+
+    fn matcher(&self) -> Matcher {
+        Matcher::new(
+            self.words
+                .iter()
+                .map(|(word, value)| (word.as_str(), *value)),
+        )
+    }
+}
+
+/// The result of [`Vocabulary::lenient_calibration_sum`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Calibration<'a> {
+    pub sum: usize,
+    /// The number, counting from one, and content of every line without any
+    /// number.
+    pub skipped: Vec<(usize, &'a str)>,
+}
+
+/// A number found in a line.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token<'a> {
+    /// Where the number is in the line, in bytes.
+    pub span: Range<usize>,
+    pub word: &'a str,
+    pub value: usize,
+}
+
+/// The numbers picked as the first and last of a line, see
+/// [`Vocabulary::trace`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineTrace<'a> {
+    pub line: &'a str,
+    pub first: Option<Token<'a>>,
+    pub last: Option<Token<'a>>,
+}
+
+impl LineTrace<'_> {
+    /// The calibration value of the line, `None` if it has no number.
+    pub fn value(&self) -> Option<usize> {
+        self.first
+            .as_ref()
+            .zip(self.last.as_ref())
+            .map(|(first, last)| calibration_value(first.value, last.value))
+    }
+}
+
+impl Display for LineTrace<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.first, &self.last, self.value()) {
+            (Some(first), Some(last), Some(value)) => write!(
+                f,
+                "{}: {} at {:?}, {} at {:?} = {value}",
+                self.line, first.word, first.span, last.word, last.span
+            ),
+            _ => write!(f, "{}: no digits", self.line),
+        }
+    }
+}
+
+fn calibration_value(first: usize, last: usize) -> usize {
+    (leading_digit(first) * 10) + (last % 10)
+}
+
+fn leading_digit(mut value: usize) -> usize {
+    while value >= 10 {
+        value /= 10;
+    }
+    value
+}
+
+fn find_numbers(line: &str, matcher: &Matcher) -> Option<(usize, usize)> {
+    let first = matcher.first(line)?;
+    let last = matcher.last(line).unwrap_or(first);
+    Some((first.value, last.value))
+}
+
+/// Finds the first and last of a set of words in a line, where words may
+/// overlap, as in `eightwo` or `twone`.
+struct Matcher {
+    forward: Automaton,
+    backward: Automaton,
+}
+
+impl Matcher {
+    /// Creates a matcher for `words`, each paired with the value it stands
+    /// for.
+    fn new<'a>(words: impl Iterator<Item = (&'a str, usize)> + Clone) -> Self {
+        let reversed = words
+            .clone()
+            .map(|(word, value)| (word.bytes().rev().collect::<Vec<_>>(), value))
+            .collect::<Vec<_>>();
+        Self {
+            forward: Automaton::new(words.map(|(word, value)| (word.as_bytes(), value))),
+            backward: Automaton::new(
+                reversed
+                    .iter()
+                    .map(|(word, value)| (word.as_slice(), *value)),
+            ),
+        }
+    }
+
+    /// The word starting earliest in `line`, the longest if several words
+    /// start there.
+    fn first(&self, line: &str) -> Option<Match> {
+        let mut first: Option<Match> = None;
+        for found in self.forward.scan(line.bytes()) {
+            if first.is_some_and(|first| found.end > first.start() + self.forward.longest) {
+                break;
+            }
+            if first.is_none_or(|first| {
+                (found.start(), Reverse(found.len)) < (first.start(), Reverse(first.len))
+            }) {
+                first = Some(found);
+            }
+        }
+        first
+    }
+
+    /// The word starting latest in `line`, the longest if several words
+    /// start there. Scanning `line` backwards the word starting latest is the
+    /// first one to end.
+    fn last(&self, line: &str) -> Option<Match> {
+        self.backward
+            .scan(line.bytes().rev())
+            .next()
+            .map(|found| Match {
+                end: line.len() - found.start(),
+                ..found
+            })
+    }
+}
+
+/// A word found by an [`Automaton`], ending just before byte `end`.
+#[derive(Debug, Clone, Copy)]
+struct Match {
+    end: usize,
+    len: usize,
+    value: usize,
+}
+
+impl Match {
+    fn start(&self) -> usize {
+        self.end - self.len
+    }
+}
+
+/// An Aho-Corasick automaton, finding every word of a vocabulary in a single
+/// pass over the input.
+struct Automaton {
+    transitions: Vec<HashMap<u8, usize>>,
+    failure: Vec<usize>,
+    /// The value and length of the longest word ending at each state.
+    output: Vec<Option<(usize, usize)>>,
+    longest: usize,
+}
+
+impl Automaton {
+    const ROOT: usize = 0;
+
+    fn new<'a>(words: impl Iterator<Item = (&'a [u8], usize)>) -> Self {
+        let mut automaton = Self {
+            transitions: vec![HashMap::new()],
+            failure: vec![Self::ROOT],
+            output: vec![None],
+            longest: 0,
+        };
+        for (word, value) in words {
+            let state = word.iter().fold(Self::ROOT, |state, &byte| {
+                if let Some(&next) = automaton.transitions[state].get(&byte) {
+                    return next;
+                }
+                automaton.transitions.push(HashMap::new());
+                automaton.failure.push(Self::ROOT);
+                automaton.output.push(None);
+                let next = automaton.transitions.len() - 1;
+                automaton.transitions[state].insert(byte, next);
+                next
             });
-            line.match_indices(&position.to_string())
-                .for_each(|(index, _)| number_positions[index] = position);
-        });
-    let mut final_nums_iter = number_positions.iter().copied().filter(|&n| n != UNKNOWN);
-    let first = final_nums_iter.next().unwrap_or(0);
-    let last = final_nums_iter.last().unwrap_or(first);
-    (first, last)
+            automaton.output[state] = Some((value, word.len()));
+            automaton.longest = automaton.longest.max(word.len());
+        }
+        automaton.build_failures();
+        automaton
+    }
+
+    /// Links every state to the state of its longest proper suffix, breadth
+    /// first so suffixes are linked before the states extending them.
+    fn build_failures(&mut self) {
+        let mut queue = self.transitions[Self::ROOT]
+            .values()
+            .copied()
+            .collect::<VecDeque<_>>();
+        while let Some(state) = queue.pop_front() {
+            let transitions = self.transitions[state]
+                .iter()
+                .map(|(&byte, &next)| (byte, next))
+                .collect::<Vec<_>>();
+            for (byte, next) in transitions {
+                let failure = self.step(self.failure[state], byte);
+                self.failure[next] = failure;
+                if self.output[next].is_none() {
+                    self.output[next] = self.output[failure];
+                }
+                queue.push_back(next);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, byte: u8) -> usize {
+        loop {
+            if let Some(&next) = self.transitions[state].get(&byte) {
+                return next;
+            }
+            if state == Self::ROOT {
+                return Self::ROOT;
+            }
+            state = self.failure[state];
+        }
+    }
+
+    /// Yields the longest word ending at each position of `input`, in order of
+    /// where they end.
+    fn scan<'a>(
+        &'a self,
+        input: impl Iterator<Item = u8> + 'a,
+    ) -> impl Iterator<Item = Match> + 'a {
+        input
+            .enumerate()
+            .scan(Self::ROOT, |state, (position, byte)| {
+                *state = self.step(*state, byte);
+                Some(self.output[*state].map(|(value, len)| Match {
+                    end: position + 1,
+                    len,
+                    value,
+                }))
+            })
+            .flatten()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{part_one, part_two};
+    use super::*;
 
     #[test]
     fn part_one_example1() {
@@ -98,4 +415,107 @@ zoneight234
         let result = part_two(input);
         assert_eq!(result, 54019);
     }
+
+    #[test]
+    fn should_find_overlapping_words() {
+        assert_eq!(part_two("eightwo"), 82);
+        assert_eq!(part_two("twone"), 21);
+        assert_eq!(part_two("oneight3sevenine"), 19);
+    }
+
+    #[test]
+    fn should_prefer_earliest_start_over_earliest_end() {
+        let matcher = Matcher::new([("seventeen", 17), ("teen", 13), ("even", 0)].into_iter());
+        assert_eq!(
+            matcher.first("xseventeen").map(|found| found.value),
+            Some(17)
+        );
+        assert_eq!(
+            matcher.last("xseventeen").map(|found| found.value),
+            Some(13)
+        );
+        let matcher = Matcher::new([("seven", 7), ("seventeen", 17)].into_iter());
+        assert_eq!(
+            matcher.first("seventeen").map(|found| found.value),
+            Some(17)
+        );
+        assert_eq!(matcher.last("seventeen").map(|found| found.value), Some(17));
+    }
+
+    #[test]
+    fn should_match_roman_numerals() {
+        let roman = Vocabulary::new().with_words([
+            ("I", 1),
+            ("II", 2),
+            ("III", 3),
+            ("IV", 4),
+            ("V", 5),
+            ("VI", 6),
+            ("VII", 7),
+            ("VIII", 8),
+            ("IX", 9),
+        ]);
+        assert_eq!(roman.calibration_sum("aIVbVc\nxIXy"), 45 + 99);
+    }
+
+    #[test]
+    fn should_join_digits_of_multi_digit_words() {
+        let teens = Vocabulary::english().with_words([("ten", 10), ("eleven", 11), ("twelve", 12)]);
+        assert_eq!(teens.calibration_sum("eleven"), 11);
+        assert_eq!(teens.calibration_sum("twelvexsix"), 16);
+        assert_eq!(teens.calibration_sum("3ten"), 30);
+    }
+
+    #[test]
+    fn empty_vocabulary_should_sum_to_zero() {
+        assert_eq!(Vocabulary::new().calibration_sum("one2three"), 0);
+    }
+
+    #[test]
+    fn strict_should_name_line_without_digits() {
+        let input = "two1nine\nabc\nxyz";
+        let error = Vocabulary::english()
+            .strict_calibration_sum(input)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "line 2 has no digits: abc");
+        let result = Vocabulary::english().strict_calibration_sum("two1nine\n7pqrstsixteen");
+        assert_eq!(result.unwrap(), 29 + 76);
+    }
+
+    #[test]
+    fn lenient_should_list_skipped_lines() {
+        let input = "1abc2\nnothing\ntreb7uchet\n";
+        let calibration = Vocabulary::digits().lenient_calibration_sum(input);
+        assert_eq!(
+            calibration,
+            Calibration {
+                sum: 12 + 77,
+                skipped: vec![(2, "nothing")]
+            }
+        );
+    }
+
+    #[test]
+    fn trace_should_report_spans_of_first_and_last() {
+        let trace = Vocabulary::english().trace("xtwone3four\nabc\n7");
+        assert_eq!(
+            trace[0].first,
+            Some(Token {
+                span: 1..4,
+                word: "two",
+                value: 2
+            })
+        );
+        assert_eq!(
+            trace[0].last,
+            Some(Token {
+                span: 7..11,
+                word: "four",
+                value: 4
+            })
+        );
+        assert_eq!(trace[0].value(), Some(24));
+        assert_eq!(trace[1].to_string(), "abc: no digits");
+        assert_eq!(trace[2].to_string(), "7: 7 at 0..1, 7 at 0..1 = 77");
+    }
 }
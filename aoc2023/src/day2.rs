@@ -1,49 +1,360 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 
 use nom::{
     bytes::complete::tag,
-    character::complete::{alpha1, char, digit1, multispace0},
+    character::complete::{alpha1, char, digit1},
     combinator::{eof, opt},
-    error::ParseError,
     multi::{many0, many_till},
-    sequence::{delimited, terminated, tuple},
+    sequence::{terminated, tuple},
     IResult,
 };
 
-#[derive(Debug)]
-struct Game {
-    id: usize,
-    matches: Vec<Vec<(usize, Color)>>,
+use crate::ws;
+
+/// A game, in which cubes are drawn from a bag and put back several times.
+///
+/// Games parse from and display as a line of the puzzle input.
+///
+/// ```
+/// use aoc2023::day2::Game;
+///
+/// let line = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green";
+/// let game = line.parse::<Game>().unwrap();
+/// assert_eq!(game.draws[1].cubes[2].color, "blue");
+/// assert_eq!(game.to_string(), line);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Game {
+    pub id: usize,
+    pub draws: Vec<Draw>,
+}
+
+/// A handful of cubes drawn from the bag in one go.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Draw {
+    pub cubes: Vec<Cube>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
-enum Color {
-    Blue,
-    Green,
-    Red,
+/// Some number of cubes of one colour, within a [`Draw`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cube {
+    pub count: usize,
+    pub color: String,
 }
 
-impl FromStr for Color {
+impl FromStr for Game {
     type Err = Box<dyn std::error::Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "blue" => Ok(Color::Blue),
-            "green" => Ok(Color::Green),
-            "red" => Ok(Color::Red),
-            _ => Err("Unexpected color!".into()),
+        let (_, game) = parse_game(s).map_err(|error| error.to_string())?;
+        Ok(game)
+    }
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Game {}: ", self.id)?;
+        for (index, draw) in self.draws.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{draw}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Draw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, cube) in self.cubes.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{cube}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Cube {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.count, self.color)
+    }
+}
+
+/// The most cubes of each colour a bag holds. A bag holds no cubes of any
+/// colour it does not name.
+///
+/// ```
+/// use aoc2023::day2::{possible_games, Bag};
+///
+/// let bag = Bag::new().with("purple", 3).with("red", 2);
+/// let input = "Game 1: 3 purple, 1 red\nGame 2: 1 purple; 1 green";
+/// assert_eq!(possible_games(input, &bag), 1);
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Bag {
+    limits: BTreeMap<String, usize>,
+}
+
+impl Bag {
+    /// A bag holding no cubes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bag of part one, holding 12 red, 13 green and 14 blue cubes.
+    pub fn puzzle() -> Self {
+        Self::new()
+            .with("red", 12)
+            .with("green", 13)
+            .with("blue", 14)
+    }
+
+    /// Sets the number of `color` cubes in the bag.
+    pub fn with(mut self, color: &str, limit: usize) -> Self {
+        self.limits.insert(color.to_owned(), limit);
+        self
+    }
+
+    /// The number of `color` cubes in the bag.
+    pub fn limit(&self, color: &str) -> usize {
+        self.limits.get(color).copied().unwrap_or(0)
+    }
+
+    /// The number of cubes in the bag.
+    pub fn cubes(&self) -> usize {
+        self.limits.values().sum()
+    }
+
+    /// The product of the number of cubes of each colour the bag names.
+    pub fn power(&self) -> usize {
+        self.limits.values().product()
+    }
+
+    /// Grows the bag, if needed, so `cube` is possible with it.
+    fn cover(self, cube: &Cube) -> Self {
+        let limit = self.limit(&cube.color).max(cube.count);
+        self.with(&cube.color, limit)
+    }
+
+    fn is_possible(&self, cube: &Cube) -> bool {
+        cube.count <= self.limit(&cube.color)
+    }
+}
+
+impl Display for Bag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, (color, limit)) in self.limits.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{limit} {color}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Game {
+    /// The smallest bag this game is possible with.
+    pub fn minimum_bag(&self) -> Bag {
+        self.draws
+            .iter()
+            .flat_map(|draw| &draw.cubes)
+            .fold(Bag::new(), Bag::cover)
+    }
+
+    /// The index of the first draw that is impossible with `bag`, `None` if
+    /// the game is possible.
+    pub fn impossible_draw(&self, bag: &Bag) -> Option<usize> {
+        self.draws
+            .iter()
+            .position(|draw| !draw.cubes.iter().all(|cube| bag.is_possible(cube)))
+    }
+}
+
+/// The share of each colour among every cube drawn in `games`.
+///
+/// Under the model of [`maximum_likelihood_bag`], these are the shares the
+/// most likely bags of more and more cubes tend to. The likelihood keeps
+/// growing with the size of the bag, so there is no most likely bag without
+/// fixing its size.
+///
+/// ```
+/// use aoc2023::day2::{color_shares, Game};
+///
+/// let games = ["Game 1: 2 red, 1 blue", "Game 2: 4 red; 3 blue"]
+///     .map(|line| line.parse::<Game>().unwrap());
+/// let shares = color_shares(&games);
+/// assert_eq!(shares["red"], 0.6);
+/// assert_eq!(shares["blue"], 0.4);
+/// ```
+pub fn color_shares(games: &[Game]) -> BTreeMap<String, f64> {
+    let totals = cubes(games).fold(BTreeMap::new(), |mut acc, cube| {
+        *acc.entry(cube.color.clone()).or_insert(0) += cube.count;
+        acc
+    });
+    let total = totals.values().sum::<usize>();
+    totals
+        .into_iter()
+        .map(|(color, count)| (color, count as f64 / total as f64))
+        .collect()
+}
+
+/// The most likely bag of exactly `cubes` cubes to have been used for every
+/// game in `games`, `None` if no bag that size is possible with every game.
+///
+/// The cubes of a draw are taken to be picked from the bag all at once, and
+/// put back before the next draw. The likelihood of a bag is then the product
+/// over every draw of the chance of picking exactly its cubes: the ways of
+/// choosing the drawn number of cubes of each colour from those in the bag,
+/// out of the ways of choosing that many cubes from the whole bag. A bag with
+/// fewer cubes of a colour than a draw shows cannot give that draw, so the
+/// most likely bag is always possible with every game. Colours never drawn
+/// only make the others rarer, so the bag holds none of them.
+///
+/// ```
+/// use aoc2023::day2::{maximum_likelihood_bag, Bag, Game};
+///
+/// let games = ["Game 1: 2 red, 1 blue", "Game 2: 4 red; 3 blue"]
+///     .map(|line| line.parse::<Game>().unwrap());
+/// let bag = Bag::new().with("red", 6).with("blue", 4);
+/// assert_eq!(maximum_likelihood_bag(&games, 10), Some(bag));
+/// assert_eq!(maximum_likelihood_bag(&games, 6), None);
+/// ```
+pub fn maximum_likelihood_bag(games: &[Game], cubes: usize) -> Option<Bag> {
+    let draws = games
+        .iter()
+        .flat_map(|game| &game.draws)
+        .collect::<Vec<_>>();
+    // With the size of the bag fixed, the ways of choosing from the whole bag
+    // are the same for every bag, and what is left is a sum over colours of
+    // terms that grow by less and less with each cube. Adding cubes one at a
+    // time to the colour gaining the most then finds the best bag.
+    let gain = |color: &str, limit: usize| {
+        draws
+            .iter()
+            .flat_map(|draw| &draw.cubes)
+            .filter(|cube| cube.color == color && cube.count > 0)
+            .map(|cube| ((limit + 1) as f64 / (limit + 1 - cube.count) as f64).ln())
+            .sum::<f64>()
+    };
+    let mut bag = self::cubes(games).fold(Bag::new(), Bag::cover);
+    if bag.cubes() > cubes {
+        return None;
+    }
+    while bag.cubes() < cubes {
+        let (color, _) = bag
+            .limits
+            .iter()
+            .map(|(color, &limit)| (color.clone(), gain(color, limit)))
+            .fold(
+                None,
+                |best: Option<(String, f64)>, (color, gain)| match best {
+                    Some((_, best_gain)) if best_gain >= gain => best,
+                    _ => Some((color, gain)),
+                },
+            )?;
+        let limit = bag.limit(&color) + 1;
+        bag = bag.with(&color, limit);
+    }
+    Some(bag)
+}
+
+fn cubes(games: &[Game]) -> impl Iterator<Item = &Cube> + Clone {
+    games
+        .iter()
+        .flat_map(|game| &game.draws)
+        .flat_map(|draw| &draw.cubes)
+}
+
+/// How each game in an input relates to a bag, see [`analyse`].
+#[derive(Debug)]
+pub struct Analysis {
+    pub games: Vec<GameAnalysis>,
+    /// See [`color_shares`].
+    pub shares: BTreeMap<String, f64>,
+    /// The number of cubes of the [`Analysis::maximum_likelihood_bag`].
+    pub cubes: usize,
+    /// The most likely bag of `cubes` cubes, see [`maximum_likelihood_bag`].
+    pub maximum_likelihood_bag: Option<Bag>,
+}
+
+/// How a single game relates to a bag.
+#[derive(Debug)]
+pub struct GameAnalysis {
+    pub id: usize,
+    pub minimum_bag: Bag,
+    /// The first draw that is impossible with the bag, see
+    /// [`Game::impossible_draw`].
+    pub impossible_draw: Option<Draw>,
+}
+
+/// Reports, for each game in `input`, the smallest bag it is possible with
+/// and the first draw that is impossible with `bag`, along with the
+/// [`color_shares`] and the [`maximum_likelihood_bag`] of `cubes` cubes over
+/// all games. Without `cubes`, the most likely bag has as many cubes as the
+/// smallest bag every game is possible with.
+pub fn analyse(input: &str, bag: &Bag, cubes: Option<usize>) -> Analysis {
+    let games = input
+        .lines()
+        .map(|line| line.parse::<Game>().unwrap())
+        .collect::<Vec<_>>();
+    let cubes = cubes.unwrap_or_else(|| self::cubes(&games).fold(Bag::new(), Bag::cover).cubes());
+    Analysis {
+        games: games
+            .iter()
+            .map(|game| GameAnalysis {
+                id: game.id,
+                minimum_bag: game.minimum_bag(),
+                impossible_draw: game
+                    .impossible_draw(bag)
+                    .map(|index| game.draws[index].clone()),
+            })
+            .collect(),
+        shares: color_shares(&games),
+        cubes,
+        maximum_likelihood_bag: maximum_likelihood_bag(&games, cubes),
+    }
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for game in &self.games {
+            write!(f, "Game {}: minimum bag {}", game.id, game.minimum_bag)?;
+            match &game.impossible_draw {
+                Some(draw) => writeln!(f, ", impossible at {draw}")?,
+                None => writeln!(f, ", possible")?,
+            }
+        }
+        write!(f, "colour shares: ")?;
+        for (index, (color, share)) in self.shares.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:.1}% {color}", share * 100.0)?;
+        }
+        write!(f, "\nmost likely bag of {} cubes: ", self.cubes)?;
+        match &self.maximum_likelihood_bag {
+            Some(bag) => write!(f, "{bag}"),
+            None => write!(f, "none is possible with every game"),
         }
     }
 }
 
 pub fn part_one(input: &str) -> usize {
+    possible_games(input, &Bag::puzzle())
+}
+
+/// Sums the ids of the games that are possible with `bag`.
+pub fn possible_games(input: &str, bag: &Bag) -> usize {
     let mut sum = 0;
     for line in input.lines() {
-        let (_, game) = parse_game(line).unwrap();
+        let game = line.parse::<Game>().unwrap();
         if game
-            .matches
+            .draws
             .iter()
-            .all(|r#match| r#match.iter().all(is_possible))
+            .all(|draw| draw.cubes.iter().all(|cube| bag.is_possible(cube)))
         {
             sum += game.id
         }
@@ -51,81 +362,212 @@ pub fn part_one(input: &str) -> usize {
     sum
 }
 
-#[derive(Default, Debug)]
-struct MaxTracker {
-    blue: usize,
-    green: usize,
-    red: usize,
-}
-
 pub fn part_two(input: &str) -> usize {
     input
         .lines()
         .map(|line| {
-            let (_, game) = parse_game(line).unwrap();
-            let tracker = game
-                .matches
-                .into_iter()
-                .fold(MaxTracker::default(), |acc, r#match| {
-                    r#match.into_iter().fold(acc, |mut acc, round| {
-                        match round {
-                            (num, Color::Blue) => acc.blue = acc.blue.max(num),
-                            (num, Color::Green) => acc.green = acc.green.max(num),
-                            (num, Color::Red) => acc.red = acc.red.max(num),
-                        };
-                        acc
-                    })
-                });
-            tracker.blue * tracker.green * tracker.red
+            let bag = line.parse::<Game>().unwrap().minimum_bag();
+            ["blue", "green", "red"]
+                .iter()
+                .map(|&color| bag.limit(color))
+                .product::<usize>()
         })
         .sum()
 }
 
-fn is_possible(round: &(usize, Color)) -> bool {
-    match *round {
-        (n, Color::Blue) => n <= 14,
-        (n, Color::Green) => n <= 13,
-        (n, Color::Red) => n <= 12,
+/// How strictly [`parse_games`] treats its input.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    /// Accepts anything the puzzle parser can make sense of.
+    #[default]
+    Lenient,
+    /// Rejects input with any [`Anomaly`], see [`validate`].
+    Strict,
+}
+
+/// Something unexpected in a game, found by [`validate`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Anomaly {
+    /// The line of the game, counting from one.
+    pub line: usize,
+    /// The column where the anomaly starts, counting from one.
+    pub column: usize,
+    pub kind: AnomalyKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AnomalyKind {
+    /// The line is not a game at all.
+    Syntax,
+    /// A `,` or `;` with no cube or draw after it.
+    TrailingSeparator(char),
+    /// A draw with no cubes.
+    EmptyDraw,
+    /// A colour given more than once in the same draw.
+    DuplicateColor(String),
+    /// A game id that does not follow the one of the previous game.
+    NonSequentialId { expected: usize, found: usize },
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            AnomalyKind::Syntax => write!(f, "not a game"),
+            AnomalyKind::TrailingSeparator(separator) => {
+                write!(f, "nothing after `{separator}`")
+            }
+            AnomalyKind::EmptyDraw => write!(f, "empty draw"),
+            AnomalyKind::DuplicateColor(color) => write!(f, "{color} given twice in one draw"),
+            AnomalyKind::NonSequentialId { expected, found } => {
+                write!(f, "expected game {expected}, found game {found}")
+            }
+        }
     }
 }
 
+/// Parses every game in `input`. In [`Mode::Strict`] this fails with every
+/// anomaly [`validate`] finds, otherwise only with lines that are not games.
+pub fn parse_games(input: &str, mode: Mode) -> Result<Vec<Game>, Vec<Anomaly>> {
+    if mode == Mode::Strict {
+        let anomalies = validate(input);
+        if !anomalies.is_empty() {
+            return Err(anomalies);
+        }
+    }
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            line.parse::<Game>().map_err(|_| {
+                vec![Anomaly {
+                    line: index + 1,
+                    column: 1,
+                    kind: AnomalyKind::Syntax,
+                }]
+            })
+        })
+        .collect()
+}
+
+/// Finds every anomaly in `input`: trailing separators, empty draws, colours
+/// repeated within a draw and game ids that do not count up from one.
+///
+/// ```
+/// use aoc2023::day2::{validate, AnomalyKind};
+///
+/// let anomalies = validate("Game 1: 1 red, 2 red;");
+/// assert_eq!(anomalies[0].kind, AnomalyKind::DuplicateColor("red".into()));
+/// assert_eq!(anomalies[0].column, 16);
+/// assert_eq!(anomalies[1].kind, AnomalyKind::TrailingSeparator(';'));
+/// assert_eq!(anomalies[1].column, 21);
+/// ```
+pub fn validate(input: &str) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    let mut expected_id = 1;
+    for (index, line) in input.lines().enumerate() {
+        let mut report = |column: usize, kind: AnomalyKind| {
+            anomalies.push(Anomaly {
+                line: index + 1,
+                column: column + 1,
+                kind,
+            })
+        };
+        // A line whose id cannot be read is taken to be the game expected
+        // there, so the games after it are not reported as out of order.
+        let Some((header, body)) = line.split_once(':') else {
+            report(0, AnomalyKind::Syntax);
+            expected_id += 1;
+            continue;
+        };
+        let Some((id, id_start)) = header.strip_prefix("Game").and_then(|id| {
+            let start = "Game".len() + id.len() - id.trim_start().len();
+            Some((id.trim().parse::<usize>().ok()?, start))
+        }) else {
+            report(0, AnomalyKind::Syntax);
+            expected_id += 1;
+            continue;
+        };
+        if id != expected_id {
+            report(
+                id_start,
+                AnomalyKind::NonSequentialId {
+                    expected: expected_id,
+                    found: id,
+                },
+            );
+        }
+        expected_id = id + 1;
+        let draws = split_with_offsets(body, ';', header.len() + 1);
+        for (draw_index, &(draw, draw_start)) in draws.iter().enumerate() {
+            if draw.trim().is_empty() {
+                if draw_index > 0 && draw_index == draws.len() - 1 {
+                    report(draw_start - 1, AnomalyKind::TrailingSeparator(';'));
+                } else {
+                    report(draw_start, AnomalyKind::EmptyDraw);
+                }
+                continue;
+            }
+            let mut colors = Vec::new();
+            for (cube, cube_start) in split_with_offsets(draw, ',', draw_start) {
+                let column = cube_start + cube.len() - cube.trim_start().len();
+                match cube.split_whitespace().collect::<Vec<_>>().as_slice() {
+                    [] => report(cube_start - 1, AnomalyKind::TrailingSeparator(',')),
+                    [count, color] if count.parse::<usize>().is_ok() => {
+                        if colors.contains(color) {
+                            report(column, AnomalyKind::DuplicateColor(color.to_string()));
+                        }
+                        colors.push(*color);
+                    }
+                    _ => report(column, AnomalyKind::Syntax),
+                }
+            }
+        }
+    }
+    anomalies
+}
+
+/// Splits `input` on `separator`, pairing every part with where it starts in
+/// the line, given `input` starts at `offset`.
+fn split_with_offsets(input: &str, separator: char, offset: usize) -> Vec<(&str, usize)> {
+    let mut start = offset;
+    input
+        .split(separator)
+        .map(|part| {
+            let part_start = start;
+            start += part.len() + separator.len_utf8();
+            (part, part_start)
+        })
+        .collect()
+}
+
 fn parse_game(input: &str) -> IResult<&str, Game> {
     let (rest, (_, num, _)) = tuple((tag("Game"), ws(digit1), ws(char(':'))))(input)?;
-    let (rest, (matches, _)) = many_till(parse_match, eof)(rest)?;
+    let (rest, (draws, _)) = many_till(parse_draw, eof)(rest)?;
     let game = Game {
         id: num.parse::<usize>().unwrap(),
-        matches,
+        draws,
     };
     Ok((rest, game))
 }
 
-fn parse_match(input: &str) -> IResult<&str, Vec<(usize, Color)>> {
-    let parse_round = terminated(tuple((ws(digit1), ws(alpha1))), opt(char(',')));
-    let (rest, colors) = terminated(many0(parse_round), opt(char(';')))(input)?;
-    let r#match: Vec<(usize, Color)> = colors
+fn parse_draw(input: &str) -> IResult<&str, Draw> {
+    let parse_cube = terminated(tuple((ws(digit1), ws(alpha1))), opt(char(',')));
+    let (rest, colors) = terminated(many0(parse_cube), opt(char(';')))(input)?;
+    let cubes = colors
         .iter()
-        .map(|(num, color)| {
-            (
-                num.parse::<usize>().unwrap(),
-                Color::from_str(color).unwrap(),
-            )
+        .map(|(num, color)| Cube {
+            count: num.parse::<usize>().unwrap(),
+            color: color.to_string(),
         })
         .collect();
-    Ok((rest, r#match))
-}
-
-fn ws<'a, F: 'a, O, E: ParseError<&'a str>>(
-    inner: F,
-) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
-where
-    F: Fn(&'a str) -> IResult<&'a str, O, E>,
-{
-    delimited(multispace0, inner, multispace0)
+    Ok((rest, Draw { cubes }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Rng;
 
     #[test]
     fn part_one_example1() {
@@ -162,4 +604,326 @@ Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green"#;
         let result = part_two(input);
         assert_eq!(result, 66363);
     }
+
+    #[test]
+    fn should_parse_any_color() {
+        let input = "Game 1: 2 teal, 1 red; 3 teal\nGame 2: 4 teal\nGame 3: 1 magenta";
+        let bag = Bag::new().with("teal", 3).with("red", 1);
+        assert_eq!(possible_games(input, &bag), 1);
+    }
+
+    #[test]
+    fn unnamed_colors_should_make_game_impossible() {
+        let input = "Game 1: 1 red\nGame 2: 1 blue";
+        assert_eq!(possible_games(input, &Bag::new().with("red", 5)), 1);
+        assert_eq!(possible_games(input, &Bag::new()), 0);
+    }
+
+    fn random_game(rng: &mut Rng) -> Game {
+        const COLORS: [&str; 6] = ["red", "green", "blue", "teal", "magenta", "x"];
+        Game {
+            id: rng.next(1000),
+            draws: (0..=rng.next(5))
+                .map(|_| Draw {
+                    cubes: (0..=rng.next(4))
+                        .map(|_| Cube {
+                            count: rng.next(100),
+                            color: COLORS[rng.next(COLORS.len())].to_owned(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn printed_games_should_parse_back() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let game = random_game(&mut rng);
+            assert_eq!(game.to_string().parse::<Game>().unwrap(), game);
+        }
+    }
+
+    #[test]
+    fn parsed_games_should_print_back() {
+        let input = include_str!("../resources/day2_part1");
+        for line in input.lines() {
+            assert_eq!(line.parse::<Game>().unwrap().to_string(), line);
+        }
+    }
+
+    #[test]
+    fn should_find_minimum_bag() {
+        let game = "Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red"
+            .parse::<Game>()
+            .unwrap();
+        let bag = game.minimum_bag();
+        assert_eq!(
+            bag,
+            Bag::new().with("red", 20).with("green", 13).with("blue", 6)
+        );
+        assert_eq!(bag.power(), 1560);
+    }
+
+    #[test]
+    fn should_find_first_impossible_draw() {
+        let game = "Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red"
+            .parse::<Game>()
+            .unwrap();
+        assert_eq!(game.impossible_draw(&Bag::puzzle()), Some(2));
+        assert_eq!(game.impossible_draw(&game.minimum_bag()), None);
+    }
+
+    /// The log of the likelihood of `bag`, as described in
+    /// [`maximum_likelihood_bag`], computed directly.
+    fn log_likelihood(games: &[Game], bag: &Bag) -> f64 {
+        let ln_choose = |n: usize, k: usize| {
+            if k > n {
+                f64::NEG_INFINITY
+            } else {
+                (0..k).map(|i| ((n - i) as f64 / (k - i) as f64).ln()).sum()
+            }
+        };
+        games
+            .iter()
+            .flat_map(|game| &game.draws)
+            .map(|draw| {
+                let drawn = draw.cubes.iter().map(|cube| cube.count).sum();
+                draw.cubes
+                    .iter()
+                    .map(|cube| ln_choose(bag.limit(&cube.color), cube.count))
+                    .sum::<f64>()
+                    - ln_choose(bag.cubes(), drawn)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn maximum_likelihood_bag_should_be_the_most_likely_of_its_size() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..50 {
+            let games = (0..3)
+                .map(|id| Game {
+                    id,
+                    draws: (0..=rng.next(3))
+                        .map(|_| Draw {
+                            cubes: ["red", "green", "blue"]
+                                .iter()
+                                .filter_map(|color| {
+                                    let count = rng.next(5);
+                                    (rng.next(3) > 0).then(|| Cube {
+                                        count,
+                                        color: color.to_string(),
+                                    })
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect::<Vec<_>>();
+            let colors = cubes(&games)
+                .fold(Bag::new(), Bag::cover)
+                .limits
+                .into_keys()
+                .collect::<Vec<_>>();
+            let minimum = minimum_cubes(&games);
+            for cubes in minimum..minimum + 6 {
+                let best = maximum_likelihood_bag(&games, cubes).unwrap();
+                assert_eq!(best.cubes(), cubes);
+                let expected = all_bags(&colors, cubes)
+                    .iter()
+                    .map(|bag| log_likelihood(&games, bag))
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let actual = log_likelihood(&games, &best);
+                assert!(
+                    (actual - expected).abs() < 1e-9,
+                    "{best} {actual} {expected}"
+                );
+            }
+            if minimum > 0 {
+                assert_eq!(maximum_likelihood_bag(&games, minimum - 1), None);
+            }
+        }
+    }
+
+    /// The number of cubes in the smallest bag every game is possible with.
+    fn minimum_cubes(games: &[Game]) -> usize {
+        cubes(games).fold(Bag::new(), Bag::cover).cubes()
+    }
+
+    /// Every bag of exactly `cubes` cubes of `colors`.
+    fn all_bags(colors: &[String], cubes: usize) -> Vec<Bag> {
+        match colors {
+            [] if cubes == 0 => vec![Bag::new()],
+            [] => vec![],
+            [color, rest @ ..] => (0..=cubes)
+                .flat_map(|count| {
+                    all_bags(rest, cubes - count)
+                        .into_iter()
+                        .map(move |bag| bag.with(color, count))
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn maximum_likelihood_bag_should_tend_to_color_shares() {
+        let input = include_str!("../resources/day2_part1");
+        let games = input
+            .lines()
+            .map(|line| line.parse::<Game>().unwrap())
+            .collect::<Vec<_>>();
+        let shares = color_shares(&games);
+        assert!((shares.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        let bag = maximum_likelihood_bag(&games, 10_000).unwrap();
+        for (color, share) in &shares {
+            let bag_share = bag.limit(color) as f64 / bag.cubes() as f64;
+            assert!((bag_share - share).abs() < 0.01, "{color}");
+        }
+        assert_eq!(maximum_likelihood_bag(&games, Bag::puzzle().cubes()), None);
+        let bag = maximum_likelihood_bag(&games, 100).unwrap();
+        assert!(games
+            .iter()
+            .all(|game| game.impossible_draw(&bag).is_none()));
+    }
+
+    #[test]
+    fn should_display_analysis() {
+        let input = "Game 1: 2 red; 1 red, 3 blue";
+        let analysis = analyse(input, &Bag::new().with("red", 2), Some(2));
+        assert_eq!(
+            analysis.to_string(),
+            "Game 1: minimum bag 3 blue, 2 red, impossible at 1 red, 3 blue\n\
+             colour shares: 50.0% blue, 50.0% red\n\
+             most likely bag of 2 cubes: none is possible with every game"
+        );
+        let analysis = analyse(input, &Bag::new().with("red", 2), Some(7));
+        assert!(analysis
+            .to_string()
+            .ends_with("most likely bag of 7 cubes: 4 blue, 3 red"));
+        let analysis = analyse(input, &Bag::new().with("red", 2), None);
+        assert!(analysis
+            .to_string()
+            .ends_with("most likely bag of 5 cubes: 3 blue, 2 red"));
+    }
+
+    #[test]
+    fn report_should_show_a_most_likely_bag_for_the_puzzle() {
+        let input = include_str!("../resources/day2_part1");
+        let analysis = analyse(input, &Bag::puzzle(), None);
+        let bag = analysis.maximum_likelihood_bag.as_ref().unwrap();
+        assert_eq!(bag.cubes(), analysis.cubes);
+        assert!(analysis.cubes > Bag::puzzle().cubes());
+        assert!(analysis.to_string().ends_with(&format!(
+            "most likely bag of {} cubes: {bag}",
+            analysis.cubes
+        )));
+        let analysis = analyse(input, &Bag::puzzle(), Some(100));
+        assert_eq!(analysis.maximum_likelihood_bag.unwrap().cubes(), 100);
+    }
+
+    #[test]
+    fn puzzle_input_should_have_no_anomalies() {
+        let input = include_str!("../resources/day2_part1");
+        assert_eq!(validate(input), vec![]);
+        assert_eq!(parse_games(input, Mode::Strict).unwrap().len(), 100);
+    }
+
+    #[test]
+    fn should_flag_each_anomaly_with_its_position() {
+        let input = "Game 1: 1 red,; ; 2 blue\nGame 3: 1 red, 1 blue, 2 red\nGame 4:";
+        let anomalies = validate(input);
+        assert_eq!(
+            anomalies,
+            vec![
+                Anomaly {
+                    line: 1,
+                    column: 14,
+                    kind: AnomalyKind::TrailingSeparator(',')
+                },
+                Anomaly {
+                    line: 1,
+                    column: 16,
+                    kind: AnomalyKind::EmptyDraw
+                },
+                Anomaly {
+                    line: 2,
+                    column: 6,
+                    kind: AnomalyKind::NonSequentialId {
+                        expected: 2,
+                        found: 3
+                    }
+                },
+                Anomaly {
+                    line: 2,
+                    column: 24,
+                    kind: AnomalyKind::DuplicateColor("red".into())
+                },
+                Anomaly {
+                    line: 3,
+                    column: 8,
+                    kind: AnomalyKind::EmptyDraw
+                },
+            ]
+        );
+        assert_eq!(
+            anomalies[3].to_string(),
+            "line 2, column 24: red given twice in one draw"
+        );
+    }
+
+    #[test]
+    fn should_resync_ids_after_a_syntax_error() {
+        let input = "Game 1: 1 red\nGame two: 1 red\nGame 3: 1 red\nGame 4 1 red\nGame   5: 1 red";
+        assert_eq!(
+            validate(input),
+            vec![
+                Anomaly {
+                    line: 2,
+                    column: 1,
+                    kind: AnomalyKind::Syntax
+                },
+                Anomaly {
+                    line: 4,
+                    column: 1,
+                    kind: AnomalyKind::Syntax
+                },
+            ]
+        );
+        assert_eq!(
+            validate("Game 1: 1 red\nGame x: 1 red\nGame   4: 1 red"),
+            vec![
+                Anomaly {
+                    line: 2,
+                    column: 1,
+                    kind: AnomalyKind::Syntax
+                },
+                Anomaly {
+                    line: 3,
+                    column: 8,
+                    kind: AnomalyKind::NonSequentialId {
+                        expected: 3,
+                        found: 4
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lenient_mode_should_accept_anomalies() {
+        let input = "Game 1: 1 red,; 2 blue;\nGame 3: 1 red, 2 red";
+        let games = parse_games(input, Mode::Lenient).unwrap();
+        assert_eq!(games[0].draws.len(), 2);
+        assert_eq!(games[1].draws[0].cubes.len(), 2);
+        assert_eq!(parse_games(input, Mode::Strict).unwrap_err().len(), 4);
+    }
+
+    #[test]
+    fn should_reject_lines_that_are_not_games() {
+        let anomalies = parse_games("Game 1: 1 red\nnonsense", Mode::Lenient).unwrap_err();
+        assert_eq!(anomalies[0].line, 2);
+        assert_eq!(anomalies[0].kind, AnomalyKind::Syntax);
+    }
 }
@@ -1,190 +1,614 @@
+use std::collections::{BTreeSet, HashMap};
+
 use nom::{
     branch::alt,
-    bytes::complete::{is_a, tag},
-    character::complete::{digit1, line_ending},
-    combinator::eof,
-    multi::{many1, many_till},
+    bytes::complete::tag,
+    character::complete::{anychar, digit1},
+    combinator::{all_consuming, recognize},
+    multi::{many0, many1},
     IResult,
 };
-use nom_locate::{position, LocatedSpan};
 
-type Span<'a> = LocatedSpan<&'a str>;
+#[derive(Debug, PartialEq)]
+enum TokenType {
+    Number,
+    Dot,
+    Symbol,
+}
 
 #[derive(Debug)]
-struct Point {
-    x: usize,
-    y: usize,
+struct Token<'a> {
+    token: &'a str,
+    token_type: TokenType,
 }
 
-impl Point {
-    fn new(x: usize, y: usize) -> Self {
-        Self { x, y }
+impl<'a> Token<'a> {
+    fn new(token: &'a str, token_type: TokenType) -> Self {
+        Self { token, token_type }
     }
+
+    /// How many cells the token takes up.
+    fn len(&self) -> usize {
+        self.token.chars().count()
+    }
+}
+
+/// A zero based row and column in a schematic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// A number in a schematic, spanning `len` cells to the right of `point`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Number {
+    pub point: Point,
+    pub len: usize,
+    pub value: usize,
+}
+
+impl Number {
+    /// The cells the digits of the number are in.
+    pub fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.len).map(|offset| Point {
+            row: self.point.row,
+            column: self.point.column + offset,
+        })
+    }
+}
+
+/// A symbol in a schematic.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub point: Point,
+    pub ch: char,
 }
 
+/// A symbol next to the right numbers to be a gear, see [`GearRules`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Gear<'a> {
+    pub symbol: &'a Symbol,
+    pub numbers: Vec<&'a Number>,
+    pub value: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cell {
+    Empty,
+    /// Part of the number at this index in [`Schematic::numbers`].
+    Number(usize),
+    /// The symbol at this index in [`Schematic::symbols`].
+    Symbol(usize),
+}
+
+/// An engine schematic laid out cell by cell, so the neighbours of any
+/// number or symbol can be looked up directly instead of searched for.
+///
+/// ```
+/// use aoc2023::day3::{GearRules, Point, Schematic, Symbols};
+///
+/// let schematic = Schematic::new("467..114..\n...*......\n..35..633.", &Symbols::default());
+/// let part_numbers = schematic.part_numbers().map(|number| number.value);
+/// assert_eq!(part_numbers.collect::<Vec<_>>(), [467, 35]);
+/// let gears = schematic.gears(&GearRules::puzzle());
+/// assert_eq!(gears[0].value, 467 * 35);
+/// assert_eq!(gears[0].symbol.point, Point { row: 1, column: 3 });
+/// let symbols = schematic.symbols_adjacent_to(gears[0].numbers[1]);
+/// assert_eq!(symbols[0].ch, '*');
+/// ```
 #[derive(Debug)]
-struct BoundingBox {
-    start: Point,
-    end: Point,
-    value: usize,
+pub struct Schematic {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    numbers: Vec<Number>,
+    symbols: Vec<Symbol>,
+    neighbourhood: Neighbourhood,
+    lines: Vec<String>,
 }
 
-impl BoundingBox {
-    fn contains(&self, point: &Point) -> bool {
-        point.x >= self.start.x
-            && point.x <= self.end.x
-            && point.y >= self.start.y
-            && point.y <= self.end.y
+impl Schematic {
+    /// Parses `input`, where `symbols` says which characters are symbols.
+    /// Neighbours are the eight cells around, see [`Self::with_neighbourhood`].
+    pub fn new(input: &str, symbols: &Symbols) -> Self {
+        let rows = input
+            .lines()
+            .map(|line| parse_line(line, symbols).unwrap().1)
+            .collect::<Vec<_>>();
+        Self::from_rows(input, &rows)
+    }
+
+    /// Lays out the tokens of each row, counting columns as it goes, so
+    /// every line is only walked once.
+    fn from_rows(input: &str, rows: &[Vec<Token<'_>>]) -> Self {
+        let height = rows.len();
+        let width = rows
+            .iter()
+            .map(|tokens| tokens.iter().map(Token::len).sum())
+            .max()
+            .unwrap_or(0);
+        let mut schematic = Self {
+            width,
+            height,
+            cells: vec![Cell::Empty; width * height],
+            numbers: Vec::new(),
+            symbols: Vec::new(),
+            neighbourhood: Neighbourhood::default(),
+            lines: input.lines().map(String::from).collect(),
+        };
+        for (row, tokens) in rows.iter().enumerate() {
+            let mut column = 0;
+            for token in tokens {
+                let point = Point { row, column };
+                let index = row * width + column;
+                column += token.len();
+                match token.token_type {
+                    TokenType::Number => {
+                        let number = schematic.numbers.len();
+                        schematic.cells[index..index + token.len()].fill(Cell::Number(number));
+                        schematic.numbers.push(Number {
+                            point,
+                            len: token.len(),
+                            value: token.token.parse::<usize>().unwrap(),
+                        });
+                    }
+                    TokenType::Symbol => {
+                        schematic.cells[index] = Cell::Symbol(schematic.symbols.len());
+                        let ch = token.token.chars().next().unwrap();
+                        schematic.symbols.push(Symbol { point, ch });
+                    }
+                    TokenType::Dot => {}
+                }
+            }
+        }
+        schematic
+    }
+
+    /// Uses `neighbourhood` to decide which cells are next to each other.
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        self
+    }
+
+    /// Every number, in reading order.
+    pub fn numbers(&self) -> &[Number] {
+        &self.numbers
+    }
+
+    /// Every symbol, in reading order.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// The numbers next to a symbol, in reading order.
+    pub fn part_numbers(&self) -> impl Iterator<Item = &Number> {
+        self.numbers
+            .iter()
+            .filter(|number| self.is_part_number(number))
+    }
+
+    /// Whether `number` is next to a symbol.
+    pub fn is_part_number(&self, number: &Number) -> bool {
+        self.around(number.points())
+            .any(|cell| matches!(cell, Cell::Symbol(_)))
+    }
+
+    /// The numbers with a digit next to `point`, in reading order.
+    pub fn numbers_adjacent_to(&self, point: Point) -> Vec<&Number> {
+        self.around(std::iter::once(point))
+            .filter_map(|cell| match cell {
+                Cell::Number(index) => Some(index),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|index| &self.numbers[index])
+            .collect()
+    }
+
+    /// The symbols next to any digit of `number`, in reading order.
+    pub fn symbols_adjacent_to(&self, number: &Number) -> Vec<&Symbol> {
+        self.around(number.points())
+            .filter_map(|cell| match cell {
+                Cell::Symbol(index) => Some(index),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|index| &self.symbols[index])
+            .collect()
+    }
+
+    /// The gears, as defined by `rules`, in reading order.
+    pub fn gears(&self, rules: &GearRules) -> Vec<Gear<'_>> {
+        self.symbols
+            .iter()
+            .filter_map(|symbol| {
+                let &(arity, reducer) = rules.rules.get(&symbol.ch)?;
+                let numbers = self.numbers_adjacent_to(symbol.point);
+                (numbers.len() == arity).then(|| Gear {
+                    symbol,
+                    value: reducer.reduce(numbers.iter().map(|number| number.value)),
+                    numbers,
+                })
+            })
+            .collect()
+    }
+
+    /// Draws the schematic with part numbers, other numbers, symbols and the
+    /// gears defined by `rules` told apart, see [`Style`].
+    ///
+    /// ```
+    /// use aoc2023::day3::{GearRules, Schematic, Style, Symbols};
+    ///
+    /// let schematic = Schematic::new("467..114..\n...*......\n..35..#...", &Symbols::default());
+    /// let markers = "\
+    /// 467..114..
+    /// PPP..nnn..
+    /// ...*......
+    /// ...G......
+    /// ..35..#...
+    /// ..PP..s...
+    /// ";
+    /// assert_eq!(schematic.render(&GearRules::puzzle(), Style::Markers), markers);
+    /// ```
+    pub fn render(&self, rules: &GearRules, style: Style) -> String {
+        let mut marks = vec![None; self.cells.len()];
+        for number in &self.numbers {
+            let mark = if self.is_part_number(number) {
+                Mark::PartNumber
+            } else {
+                Mark::Number
+            };
+            for point in number.points() {
+                marks[point.row * self.width + point.column] = Some(mark);
+            }
+        }
+        for symbol in &self.symbols {
+            marks[symbol.point.row * self.width + symbol.point.column] = Some(Mark::Symbol);
+        }
+        for gear in self.gears(rules) {
+            marks[gear.symbol.point.row * self.width + gear.symbol.point.column] = Some(Mark::Gear);
+        }
+        let mut rendered = String::new();
+        for (row, line) in self.lines.iter().enumerate() {
+            let marks = line
+                .chars()
+                .enumerate()
+                .map(|(column, ch)| (ch, marks[row * self.width + column]));
+            match style {
+                Style::Ansi => {
+                    let mut current = None;
+                    for (ch, mark) in marks {
+                        if mark != current {
+                            rendered.push_str(mark.map_or(RESET, Mark::colour));
+                            current = mark;
+                        }
+                        rendered.push(ch);
+                    }
+                    if current.is_some() {
+                        rendered.push_str(RESET);
+                    }
+                    rendered.push('\n');
+                }
+                Style::Markers => {
+                    rendered.push_str(line);
+                    rendered.push('\n');
+                    rendered.extend(marks.map(|(ch, mark)| mark.map_or(ch, Mark::marker)));
+                    rendered.push('\n');
+                }
+            }
+        }
+        rendered
+    }
+
+    /// The cells in the neighbourhood of any of `points`. Cells may be
+    /// repeated.
+    fn around<'a>(
+        &'a self,
+        points: impl Iterator<Item = Point> + 'a,
+    ) -> impl Iterator<Item = Cell> + 'a {
+        let offsets = self.neighbourhood.offsets();
+        points.flat_map(move |point| {
+            offsets.clone().into_iter().filter_map(move |(dr, dc)| {
+                let row = self.shift(point.row, dr, self.height)?;
+                let column = self.shift(point.column, dc, self.width)?;
+                Some(self.cells[row * self.width + column])
+            })
+        })
+    }
+
+    /// Moves `index` by `offset` along an axis of length `len`, wrapping
+    /// around on a torus and falling off the edge otherwise.
+    fn shift(&self, index: usize, offset: isize, len: usize) -> Option<usize> {
+        let index = index as isize + offset;
+        if self.neighbourhood.wrap {
+            Some(index.rem_euclid(len as isize) as usize)
+        } else {
+            (0..len as isize).contains(&index).then_some(index as usize)
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum TokenType {
+/// How [`Schematic::render`] tells the kinds of cells apart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Part numbers in green, other numbers in red, symbols in yellow and
+    /// gears in bold magenta.
+    #[default]
+    Ansi,
+    /// Plain text, each line followed by a line of markers: `P` under the
+    /// digits of part numbers, `n` under other numbers, `s` under symbols and
+    /// `G` under gears.
+    Markers,
+}
+
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    PartNumber,
     Number,
-    Dot,
     Symbol,
+    Gear,
 }
 
-#[derive(Debug)]
-struct Token<'a> {
-    position: Span<'a>,
-    token: &'a str,
-    token_type: TokenType,
+impl Mark {
+    fn colour(self) -> &'static str {
+        match self {
+            Mark::PartNumber => "\x1b[32m",
+            Mark::Number => "\x1b[31m",
+            Mark::Symbol => "\x1b[33m",
+            Mark::Gear => "\x1b[1;35m",
+        }
+    }
+
+    fn marker(self) -> char {
+        match self {
+            Mark::PartNumber => 'P',
+            Mark::Number => 'n',
+            Mark::Symbol => 's',
+            Mark::Gear => 'G',
+        }
+    }
 }
 
-impl<'a> Token<'a> {
-    fn new(position: Span<'a>, token: &'a str, token_type: TokenType) -> Self {
+/// Which cells count as next to a cell.
+///
+/// Numbers and symbols use the same neighbourhood: a number is next to a
+/// symbol when the symbol is in the neighbourhood of any of its digits, which
+/// is the case exactly when one of its digits is in the neighbourhood of the
+/// symbol.
+///
+/// ```
+/// use aoc2023::day3::{sum_part_numbers, Neighbourhood, Symbols};
+///
+/// let input = "1..\n.*.\n..2";
+/// let part_numbers = |neighbourhood| sum_part_numbers(input, &Symbols::default(), &neighbourhood);
+/// assert_eq!(part_numbers(Neighbourhood::eight()), 3);
+/// assert_eq!(part_numbers(Neighbourhood::four()), 0);
+/// assert_eq!(part_numbers(Neighbourhood::manhattan(2)), 3);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbourhood {
+    shape: Shape,
+    wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    /// Cells at most this many steps away, moving up, down, left or right.
+    Manhattan(usize),
+    /// The eight cells around, diagonals included.
+    Moore,
+}
+
+impl Default for Neighbourhood {
+    fn default() -> Self {
+        Self::eight()
+    }
+}
+
+impl Neighbourhood {
+    /// The cells above, below, left and right.
+    pub fn four() -> Self {
+        Self::manhattan(1)
+    }
+
+    /// The cells around, diagonals included, as in the puzzle.
+    pub fn eight() -> Self {
         Self {
-            position,
-            token,
-            token_type,
+            shape: Shape::Moore,
+            wrap: false,
+        }
+    }
+
+    /// The cells at a manhattan distance of at most `radius`.
+    pub fn manhattan(radius: usize) -> Self {
+        Self {
+            shape: Shape::Manhattan(radius),
+            wrap: false,
+        }
+    }
+
+    /// The same neighbourhood on a torus, where the last row is next to the
+    /// first and the last column next to the first.
+    pub fn wrapping(self) -> Self {
+        Self { wrap: true, ..self }
+    }
+
+    /// The row and column offsets of the neighbours of a cell.
+    fn offsets(self) -> Vec<(isize, isize)> {
+        let radius = match self.shape {
+            Shape::Manhattan(radius) => radius as isize,
+            Shape::Moore => 1,
+        };
+        (-radius..=radius)
+            .flat_map(|dr| (-radius..=radius).map(move |dc| (dr, dc)))
+            .filter(|&(dr, dc)| (dr, dc) != (0, 0))
+            .filter(|&(dr, dc)| match self.shape {
+                Shape::Manhattan(radius) => dr.unsigned_abs() + dc.unsigned_abs() <= radius,
+                Shape::Moore => true,
+            })
+            .collect()
+    }
+}
+
+/// Which characters of a schematic are symbols.
+#[derive(Debug, Default, Clone)]
+pub enum Symbols {
+    /// Any character other than a digit or `.`.
+    #[default]
+    AnyOther,
+    /// Only these characters, anything else that is not a digit is treated
+    /// like a `.`.
+    Only(Vec<char>),
+}
+
+impl Symbols {
+    /// Only the characters in `symbols` are symbols.
+    pub fn only(symbols: &str) -> Self {
+        Symbols::Only(symbols.chars().collect())
+    }
+
+    fn contains(&self, ch: char) -> bool {
+        match self {
+            Symbols::AnyOther => !ch.is_ascii_digit() && ch != '.',
+            Symbols::Only(symbols) => symbols.contains(&ch),
         }
     }
 }
 
 pub fn part_one(input: &str) -> usize {
-    let span = Span::new(input);
-    let (_, tokens) = parse(span).unwrap();
-    let tokens = tokens
-        .into_iter()
-        .flat_map(|(tokens, _)| tokens)
-        .collect::<Vec<Token<'_>>>();
-    let boxes = build_bounding_boxes(&tokens);
-    let symbols = build_symbol_points(&tokens);
-    boxes
-        .into_iter()
-        .flat_map(|r#box| {
-            symbols
-                .iter()
-                .any(|symbol| r#box.contains(symbol))
-                .then_some(r#box.value)
-                .or(Some(0))
-        })
-        .sum()
+    sum_part_numbers(input, &Symbols::default(), &Neighbourhood::default())
 }
 
 pub fn part_two(input: &str) -> usize {
-    let span = Span::new(input);
-    let (_, tokens) = parse(span).unwrap();
-    let tokens = tokens
-        .into_iter()
-        .flat_map(|(tokens, _)| tokens)
-        .collect::<Vec<Token<'_>>>();
-    let boxes = build_bounding_boxes(&tokens);
-    let symbols = build_symbol_points(&tokens);
-    symbols
-        .into_iter()
-        .map(|symbol| compute_product_for_symbol(symbol, &boxes))
-        .sum()
+    sum_gears(
+        input,
+        &Symbols::default(),
+        &Neighbourhood::default(),
+        &GearRules::puzzle(),
+    )
 }
 
-fn compute_product_for_symbol(symbol: Point, boxes: &[BoundingBox]) -> usize {
-    boxes
-        .iter()
-        .try_fold((0, 1), |acc @ (count, intermediate), r#box| {
-            if count > 2 {
-                return None;
-            }
-            if r#box.contains(&symbol) {
-                Some((count + 1, intermediate * r#box.value))
-            } else {
-                Some(acc)
-            }
-        })
-        .filter(|(count, _)| *count == 2)
-        .unwrap_or((0, 0))
-        .1
+/// How the numbers next to a gear combine into its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reducer {
+    Product,
+    Sum,
+    Max,
+    /// The largest number divided by the smallest, rounded down.
+    Ratio,
 }
 
-fn filter_map_tokens<T>(
-    tokens: &[Token<'_>],
-    filter_on: TokenType,
-    f: impl FnMut(&Token) -> T,
-) -> Vec<T> {
-    tokens
-        .iter()
-        .filter(|token| token.token_type == filter_on)
-        .map(f)
-        .collect::<Vec<T>>()
-}
-
-fn build_bounding_boxes(tokens: &[Token<'_>]) -> Vec<BoundingBox> {
-    let f = |token: &Token| {
-        let line = token.position.location_line() as usize;
-        let column = token.position.get_column();
-        let length = token.token.len();
-        let number = token.token.parse::<usize>().unwrap();
-        BoundingBox {
-            start: Point::new(column.saturating_sub(length + 2), line.saturating_sub(2)),
-            end: Point::new(column - 1, line),
-            value: number,
+impl Reducer {
+    fn reduce(self, values: impl Iterator<Item = usize>) -> usize {
+        match self {
+            Reducer::Product => values.product(),
+            Reducer::Sum => values.sum(),
+            Reducer::Max => values.max().unwrap_or(0),
+            Reducer::Ratio => {
+                let (min, max) = values.fold((usize::MAX, 0), |(min, max), value| {
+                    (min.min(value), max.max(value))
+                });
+                max.checked_div(min).unwrap_or(0)
+            }
         }
-    };
-    filter_map_tokens(tokens, TokenType::Number, f)
+    }
 }
 
-fn build_symbol_points(tokens: &[Token<'_>]) -> Vec<Point> {
-    let f = |token: &Token| {
-        let line = token.position.location_line() as usize;
-        let column = token.position.get_column();
-        Point::new(column - 2, line - 1)
-    };
-    filter_map_tokens(tokens, TokenType::Symbol, f)
+/// Which symbols are gears: how many numbers a symbol must be next to and how
+/// those numbers combine.
+///
+/// ```
+/// use aoc2023::day3::{sum_gears, GearRules, Neighbourhood, Reducer, Symbols};
+///
+/// let rules = GearRules::puzzle().with('+', 3, Reducer::Sum);
+/// let input = "2.3\n.+.\n.4.\n...\n6*5";
+/// let gears = sum_gears(input, &Symbols::default(), &Neighbourhood::default(), &rules);
+/// assert_eq!(gears, 9 + 30);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct GearRules {
+    rules: HashMap<char, (usize, Reducer)>,
 }
 
-fn parse(span: Span) -> IResult<Span, Vec<(Vec<Token<'_>>, Span<'_>)>> {
-    let (rest, (tokens, _)) = many_till(
-        many_till(
-            alt((parse_number, parse_dot, parse_symbol)),
-            alt((line_ending, eof)),
-        ),
-        eof,
-    )(span)?;
-    Ok((rest, tokens))
+impl GearRules {
+    /// No symbol is a gear.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The rule of part two, a `*` next to exactly two numbers is worth their
+    /// product.
+    pub fn puzzle() -> Self {
+        Self::new().with('*', 2, Reducer::Product)
+    }
+
+    /// Makes `symbol` a gear when it is next to exactly `arity` numbers,
+    /// combined with `reducer`.
+    pub fn with(mut self, symbol: char, arity: usize, reducer: Reducer) -> Self {
+        self.rules.insert(symbol, (arity, reducer));
+        self
+    }
+}
+
+/// Sums every number with a symbol in its `neighbourhood`.
+pub fn sum_part_numbers(input: &str, symbols: &Symbols, neighbourhood: &Neighbourhood) -> usize {
+    Schematic::new(input, symbols)
+        .with_neighbourhood(*neighbourhood)
+        .part_numbers()
+        .map(|number| number.value)
+        .sum()
+}
+
+/// Sums the value of every gear, as defined by `rules`, counting the numbers
+/// in the `neighbourhood` of each symbol.
+pub fn sum_gears(
+    input: &str,
+    symbols: &Symbols,
+    neighbourhood: &Neighbourhood,
+    rules: &GearRules,
+) -> usize {
+    Schematic::new(input, symbols)
+        .with_neighbourhood(*neighbourhood)
+        .gears(rules)
+        .iter()
+        .map(|gear| gear.value)
+        .sum()
 }
 
-fn parse_number(span: Span) -> IResult<Span, Token> {
-    let (rest, number) = digit1(span)?;
-    let (rest, position) = position(rest)?;
-    Ok((
-        rest,
-        Token::new(position, number.fragment(), TokenType::Number),
-    ))
+fn parse_line<'a>(line: &'a str, symbols: &Symbols) -> IResult<&'a str, Vec<Token<'a>>> {
+    all_consuming(many0(alt((parse_number, parse_dot, parse_symbol(symbols)))))(line)
 }
 
-fn parse_dot(input: Span) -> IResult<Span, Token> {
-    let (rest, _) = many1(tag("."))(input)?;
-    let (rest, position) = position(rest)?;
-    Ok((rest, Token::new(position, ".", TokenType::Dot)))
+fn parse_number(input: &str) -> IResult<&str, Token<'_>> {
+    let (rest, number) = digit1(input)?;
+    Ok((rest, Token::new(number, TokenType::Number)))
 }
 
-fn parse_symbol(span: Span) -> IResult<Span, Token> {
-    let (rest, symbol) = is_a("#*+$&-%/=@")(span)?;
-    let (rest, position) = position(rest)?;
-    Ok((
-        rest,
-        Token::new(position, symbol.fragment(), TokenType::Symbol),
-    ))
+fn parse_dot(input: &str) -> IResult<&str, Token<'_>> {
+    let (rest, dots) = recognize(many1(tag(".")))(input)?;
+    Ok((rest, Token::new(dots, TokenType::Dot)))
+}
+
+/// Parses any single character, as a symbol if it is one of `symbols` and as
+/// a dot otherwise.
+fn parse_symbol<'a, 's>(
+    symbols: &'s Symbols,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Token<'a>> + 's {
+    move |input| {
+        let (rest, symbol) = recognize(anychar)(input)?;
+        let ch = symbol.chars().next().unwrap();
+        let token_type = if symbols.contains(ch) {
+            TokenType::Symbol
+        } else {
+            TokenType::Dot
+        };
+        Ok((rest, Token::new(symbol, token_type)))
+    }
 }
 
 #[cfg(test)]
@@ -236,4 +660,297 @@ mod tests {
         let result = part_two(input);
         assert_eq!(result, 78236071);
     }
+
+    #[test]
+    fn should_scale_to_wide_schematics() {
+        let row = "12*34.".repeat(2000);
+        let input = format!("{row}\n{}\n{row}", ".".repeat(row.len()));
+        assert_eq!(part_one(&input), 2 * 2000 * (12 + 34));
+        assert_eq!(part_two(&input), 2 * 2000 * 12 * 34);
+    }
+
+    /// Times parsing schematics of growing width, which should take time in
+    /// proportion to the width rather than its square. Run with
+    /// `cargo test --release -p aoc2023 day3 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_parse_width() {
+        use std::{hint::black_box, time::Instant};
+        const ITERATIONS: u32 = 10;
+        for repeats in [2_000, 4_000, 8_000, 16_000] {
+            let row = "12*34.".repeat(repeats);
+            let input = format!("{row}\n{row}\n{row}");
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                black_box(Schematic::new(black_box(&input), &Symbols::default()));
+            }
+            let elapsed = start.elapsed() / ITERATIONS;
+            println!("day3 parse of {} columns: {elapsed:?}", row.len());
+        }
+    }
+
+    #[test]
+    fn should_treat_unseen_characters_as_symbols() {
+        let input = "12!..\n...^5\n7....";
+        assert_eq!(part_one(input), 17);
+    }
+
+    #[test]
+    fn should_count_columns_in_characters() {
+        assert_eq!(part_one("é5"), 5);
+        assert_eq!(part_one("éé.\n...5"), 0);
+    }
+
+    #[test]
+    fn should_only_use_explicit_symbols() {
+        let input = "12!..\n...^5\n7*...";
+        assert_eq!(
+            sum_part_numbers(input, &Symbols::only("^"), &Neighbourhood::default()),
+            5
+        );
+        assert_eq!(
+            sum_part_numbers(input, &Symbols::only("*!"), &Neighbourhood::default()),
+            19
+        );
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::only("*"),
+                &Neighbourhood::default(),
+                &GearRules::puzzle()
+            ),
+            0
+        );
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::default(),
+                &Neighbourhood::default(),
+                &GearRules::puzzle()
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn should_parse_adjacent_symbols_separately() {
+        let input = "2.3\n.*!\n4..";
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::only("*"),
+                &Neighbourhood::default(),
+                &GearRules::puzzle()
+            ),
+            0
+        );
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::only("*!"),
+                &Neighbourhood::default(),
+                &GearRules::puzzle()
+            ),
+            0
+        );
+        assert_eq!(part_two("2..\n.*!\n..4"), 8);
+    }
+
+    #[test]
+    fn should_only_count_symbols_with_a_rule() {
+        let input = "2.3\n.#.\n...\n4*5";
+        assert_eq!(part_two(input), 20);
+        let rules = GearRules::puzzle().with('#', 2, Reducer::Product);
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::default(),
+                &Neighbourhood::default(),
+                &rules
+            ),
+            26
+        );
+    }
+
+    #[test]
+    fn should_reduce_gear_numbers() {
+        let input = "12.3\n..*.\n..5.";
+        let gears = |reducer| {
+            let rules = GearRules::new().with('*', 3, reducer);
+            sum_gears(
+                input,
+                &Symbols::default(),
+                &Neighbourhood::default(),
+                &rules,
+            )
+        };
+        assert_eq!(gears(Reducer::Product), 180);
+        assert_eq!(gears(Reducer::Sum), 20);
+        assert_eq!(gears(Reducer::Max), 12);
+        assert_eq!(gears(Reducer::Ratio), 4);
+        let rules = GearRules::new().with('*', 2, Reducer::Sum);
+        assert_eq!(
+            sum_gears(
+                input,
+                &Symbols::default(),
+                &Neighbourhood::default(),
+                &rules
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn should_use_the_neighbourhood_for_numbers_and_symbols() {
+        let input = "1...\n.*..\n...2\n..3.";
+        let sums = |neighbourhood| {
+            (
+                sum_part_numbers(input, &Symbols::default(), &neighbourhood),
+                sum_gears(
+                    input,
+                    &Symbols::default(),
+                    &neighbourhood,
+                    &GearRules::puzzle(),
+                ),
+            )
+        };
+        assert_eq!(sums(Neighbourhood::eight()), (1, 0));
+        assert_eq!(sums(Neighbourhood::four()), (0, 0));
+        assert_eq!(sums(Neighbourhood::manhattan(2)), (1, 0));
+        assert_eq!(sums(Neighbourhood::manhattan(3)), (6, 0));
+        assert_eq!(sums(Neighbourhood::manhattan(3).wrapping()), (6, 0));
+    }
+
+    #[test]
+    fn should_wrap_around_a_torus() {
+        let input = "*..\n...\n..7";
+        assert_eq!(
+            sum_part_numbers(input, &Symbols::default(), &Neighbourhood::eight()),
+            0
+        );
+        let torus = Neighbourhood::eight().wrapping();
+        assert_eq!(sum_part_numbers(input, &Symbols::default(), &torus), 7);
+        assert_eq!(
+            sum_part_numbers(
+                input,
+                &Symbols::default(),
+                &Neighbourhood::four().wrapping()
+            ),
+            0
+        );
+        let input = "4.*.5\n.....\n.....\n..3..";
+        let gears = |neighbourhood| {
+            let rules = GearRules::new().with('*', 3, Reducer::Sum);
+            sum_gears(input, &Symbols::default(), &neighbourhood, &rules)
+        };
+        assert_eq!(gears(Neighbourhood::manhattan(2)), 0);
+        assert_eq!(gears(Neighbourhood::manhattan(2).wrapping()), 12);
+    }
+
+    #[test]
+    fn should_count_a_number_once_however_many_digits_are_near() {
+        let input = "123\n.*.";
+        let rules = GearRules::new().with('*', 1, Reducer::Sum);
+        let gears = |neighbourhood| sum_gears(input, &Symbols::default(), &neighbourhood, &rules);
+        assert_eq!(gears(Neighbourhood::manhattan(5)), 123);
+        assert_eq!(gears(Neighbourhood::manhattan(5).wrapping()), 123);
+    }
+
+    #[test]
+    fn should_locate_numbers_and_symbols() {
+        let schematic = Schematic::new("..12\n#...\n.3.*", &Symbols::default());
+        let numbers = schematic.numbers();
+        assert_eq!(
+            numbers,
+            [
+                Number {
+                    point: Point { row: 0, column: 2 },
+                    len: 2,
+                    value: 12,
+                },
+                Number {
+                    point: Point { row: 2, column: 1 },
+                    len: 1,
+                    value: 3,
+                },
+            ]
+        );
+        assert_eq!(
+            schematic.symbols(),
+            [
+                Symbol {
+                    point: Point { row: 1, column: 0 },
+                    ch: '#',
+                },
+                Symbol {
+                    point: Point { row: 2, column: 3 },
+                    ch: '*',
+                },
+            ]
+        );
+        assert_eq!(schematic.part_numbers().collect::<Vec<_>>(), [&numbers[1]]);
+        assert!(schematic.symbols_adjacent_to(&numbers[0]).is_empty());
+        assert_eq!(
+            schematic.symbols_adjacent_to(&numbers[1]),
+            [&schematic.symbols()[0]]
+        );
+    }
+
+    #[test]
+    fn should_find_numbers_adjacent_to_any_point() {
+        let schematic = Schematic::new("1.22\n....\n333.", &Symbols::default());
+        let adjacent = |row, column| {
+            schematic
+                .numbers_adjacent_to(Point { row, column })
+                .iter()
+                .map(|number| number.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(adjacent(1, 1), [1, 22, 333]);
+        assert_eq!(adjacent(0, 1), [1, 22]);
+        assert_eq!(adjacent(2, 3), [333]);
+        assert!(adjacent(0, 0).is_empty());
+    }
+
+    #[test]
+    fn should_list_gears_with_their_numbers() {
+        let schematic = Schematic::new("2.3\n.*.\n...\n.*4", &Symbols::default());
+        let gears = schematic.gears(&GearRules::puzzle());
+        assert_eq!(gears.len(), 1);
+        assert_eq!(gears[0].symbol, &schematic.symbols()[0]);
+        let values = gears[0].numbers.iter().map(|number| number.value);
+        assert_eq!(values.collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(gears[0].value, 6);
+    }
+
+    #[test]
+    fn should_locate_cells_by_character() {
+        let schematic = Schematic::new("é..\n..5", &Symbols::default());
+        assert_eq!(schematic.symbols()[0].point, Point { row: 0, column: 0 });
+        assert_eq!(schematic.numbers()[0].point, Point { row: 1, column: 2 });
+        assert_eq!(schematic.part_numbers().count(), 0);
+        let torus = schematic.with_neighbourhood(Neighbourhood::eight().wrapping());
+        assert_eq!(torus.part_numbers().count(), 1);
+    }
+
+    #[test]
+    fn should_render_with_ansi_colours() {
+        let schematic = Schematic::new("1.2.3\n*.+..\n.....", &Symbols::default());
+        let rules = GearRules::new().with('+', 1, Reducer::Sum);
+        assert_eq!(
+            schematic.render(&rules, Style::Ansi),
+            "\x1b[32m1\x1b[0m.\x1b[32m2\x1b[0m.\x1b[31m3\x1b[0m\n\
+             \x1b[33m*\x1b[0m.\x1b[1;35m+\x1b[0m..\n\
+             .....\n"
+        );
+    }
+
+    #[test]
+    fn should_mark_lines_of_different_lengths() {
+        let schematic = Schematic::new("12*\n.\n7..#5", &Symbols::default());
+        assert_eq!(
+            schematic.render(&GearRules::puzzle(), Style::Markers),
+            "12*\nPPs\n.\n.\n7..#5\nn..sP\n"
+        );
+    }
 }
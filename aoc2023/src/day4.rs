@@ -1,75 +1,313 @@
-use std::collections::HashSet;
-
-pub fn part_one(input: &str) -> usize {
-    parse(input)
-        .map(|matches| {
-            let count = matches.len();
-            if count != 0 {
-                2usize.pow(count as u32 - 1)
-            } else {
-                0
-            }
+use std::{collections::HashMap, error::Error, fmt::Display, str::FromStr};
+
+/// A scratchcard, its numbers kept as the bits of a `u128` so matching them
+/// is a single `and` and a popcount.
+///
+/// ```
+/// use aoc2023::day4::Scratchcard;
+///
+/// let card = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53";
+/// let card = card.parse::<Scratchcard>().unwrap();
+/// assert_eq!(card.matching().collect::<Vec<_>>(), [17, 48, 83, 86]);
+/// assert_eq!(card.points(), 8);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scratchcard {
+    id: usize,
+    winning: u128,
+    held: u128,
+}
+
+impl Scratchcard {
+    /// The largest number a card can have.
+    pub const MAX_NUMBER: usize = 127;
+
+    /// The number of the card.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The winning numbers, in increasing order.
+    pub fn winning(&self) -> impl Iterator<Item = usize> {
+        numbers(self.winning)
+    }
+
+    /// The numbers held, in increasing order.
+    pub fn held(&self) -> impl Iterator<Item = usize> {
+        numbers(self.held)
+    }
+
+    /// The winning numbers that are held, in increasing order.
+    pub fn matching(&self) -> impl Iterator<Item = usize> {
+        numbers(self.winning & self.held)
+    }
+
+    /// How many winning numbers are held.
+    pub fn matches(&self) -> usize {
+        (self.winning & self.held).count_ones() as usize
+    }
+
+    /// One point for the first match, doubled for each match after it. A card
+    /// can match every number up to [`Scratchcard::MAX_NUMBER`], so the
+    /// points need as many bits.
+    pub fn points(&self) -> u128 {
+        match self.matches() {
+            0 => 0,
+            matches => 1 << (matches - 1),
+        }
+    }
+}
+
+impl FromStr for Scratchcard {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (card, numbers) = s.split_once(':').ok_or("missing ':'")?;
+        let id = card
+            .strip_prefix("Card")
+            .and_then(|id| id.trim().parse().ok())
+            .ok_or_else(|| format!("invalid card id {card:?}"))?;
+        let (winning, held) = numbers.split_once('|').ok_or("missing '|'")?;
+        Ok(Self {
+            id,
+            winning: parse_numbers(winning)?,
+            held: parse_numbers(held)?,
         })
-        .sum::<usize>()
+    }
 }
 
-pub fn part_two(input: &str) -> usize {
-    let matching = parse(input).fold(Vec::new(), |mut acc, matches| {
-        acc.push(matches);
-        acc
-    });
-    let copies = vec![1; matching.len()];
-    matching
-        .iter()
+/// The positions of the set bits of `bits`, lowest first.
+fn numbers(mut bits: u128) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        (bits != 0).then(|| {
+            let number = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            number
+        })
+    })
+}
+
+/// What happens when a card would win copies of cards past the end of the
+/// table.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The copies past the last card are lost.
+    #[default]
+    Truncate,
+    /// The table is rejected, the puzzle promises it never happens.
+    Reject,
+}
+
+/// Parses a table of cards, numbered from 1 in order, one per line.
+///
+/// ```
+/// use aoc2023::day4::{parse_cards, Overflow};
+///
+/// let input = "Card 1: 1 2 | 1 2\nCard 2: 3 | 3";
+/// assert_eq!(parse_cards(input, Overflow::Truncate).unwrap().len(), 2);
+/// let error = parse_cards(input, Overflow::Reject).unwrap_err();
+/// assert_eq!(error.to_string(), "line 1: card 1 would copy cards past card 2");
+/// ```
+pub fn parse_cards(input: &str, overflow: Overflow) -> Result<Vec<Scratchcard>, Box<dyn Error>> {
+    let mut lines = HashMap::new();
+    let cards = input
+        .lines()
         .enumerate()
-        .fold(copies, |mut acc, (index, matches)| {
-            let times = acc[index];
-            for _ in 0..times {
-                acc.iter_mut()
-                    .skip(index + 1)
-                    .take(matches.len())
-                    .for_each(|copies| *copies += 1);
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let card = line
+                .parse::<Scratchcard>()
+                .map_err(|error| format!("line {line_number}: {error}"))?;
+            if let Some(first) = lines.insert(card.id, line_number) {
+                return Err(format!(
+                    "line {line_number}: card {} is already on line {first}",
+                    card.id
+                ));
             }
-            acc
+            if card.id != index + 1 {
+                return Err(format!(
+                    "line {line_number}: expected card {}, found card {}",
+                    index + 1,
+                    card.id
+                ));
+            }
+            Ok(card)
         })
-        .into_iter()
-        .sum()
+        .collect::<Result<Vec<_>, _>>()?;
+    if overflow == Overflow::Reject {
+        if let Some(card) = cards
+            .iter()
+            .find(|card| card.id + card.matches() > cards.len())
+        {
+            return Err(format!(
+                "line {}: card {} would copy cards past card {}",
+                card.id,
+                card.id,
+                cards.len()
+            )
+            .into());
+        }
+    }
+    Ok(cards)
 }
 
-fn parse(input: &str) -> impl Iterator<Item = HashSet<usize>> + '_ {
-    input.lines().map(|line| {
-        let line = parse_card(line);
-        let &[winning, numbers] = line.split('|').collect::<Vec<&str>>().as_slice() else {
-            unreachable!();
-        };
-        let winning = parse_numbers(winning);
-        let numbers = parse_numbers(numbers);
-        winning
-            .intersection(&numbers)
-            .copied()
-            .collect::<HashSet<usize>>()
+/// How many cards are held in the end, including the copies won.
+pub fn total_scratchcards(input: &str, overflow: Overflow) -> Result<usize, Box<dyn Error>> {
+    let matches = parse_cards(input, overflow)?
+        .iter()
+        .map(Scratchcard::matches)
+        .collect::<Vec<_>>();
+    Ok(copies(&matches).iter().sum())
+}
+
+/// How a card scored, see [`report`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct CardReport {
+    pub id: usize,
+    pub matches: usize,
+    pub points: u128,
+    /// How many of this card are held in the end, the original included.
+    pub copies: usize,
+    /// The ids of the earlier cards that won copies of this one.
+    pub copied_from: Vec<usize>,
+}
+
+/// How every card of a table scored.
+#[derive(Debug)]
+pub struct Report {
+    pub cards: Vec<CardReport>,
+}
+
+impl Report {
+    /// How many cards are held in the end.
+    pub fn total(&self) -> usize {
+        self.cards.iter().map(|card| card.copies).sum()
+    }
+}
+
+/// Reports the matches, points and copies of each card in `input`, and which
+/// earlier cards won those copies.
+///
+/// ```
+/// use aoc2023::day4::{report, Overflow};
+///
+/// let input = "Card 1: 1 2 | 1 2\nCard 2: 3 | 3\nCard 3: 4 | 5";
+/// let report = report(input, Overflow::Reject).unwrap();
+/// assert_eq!(report.cards[2].copied_from, [1, 2]);
+/// assert_eq!(report.cards[2].copies, 4);
+/// assert_eq!(report.total(), 7);
+/// ```
+pub fn report(input: &str, overflow: Overflow) -> Result<Report, Box<dyn Error>> {
+    let cards = parse_cards(input, overflow)?;
+    let matches = cards.iter().map(Scratchcard::matches).collect::<Vec<_>>();
+    let mut copied_from = vec![Vec::new(); cards.len()];
+    for (index, card) in cards.iter().enumerate() {
+        let won = index + 1..(index + 1 + card.matches()).min(cards.len());
+        for from in &mut copied_from[won] {
+            from.push(card.id);
+        }
+    }
+    Ok(Report {
+        cards: cards
+            .iter()
+            .zip(copies(&matches))
+            .zip(copied_from)
+            .map(|((card, copies), copied_from)| CardReport {
+                id: card.id,
+                matches: card.matches(),
+                points: card.points(),
+                copies,
+                copied_from,
+            })
+            .collect(),
     })
 }
 
-fn parse_card(input: &str) -> String {
-    input
-        .chars()
-        .skip_while(|&c| c != ':')
-        .skip(1)
-        .collect::<String>()
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn width(header: &str, values: impl Iterator<Item = impl Display>) -> usize {
+            values
+                .map(|value| value.to_string().len())
+                .fold(header.len(), usize::max)
+        }
+        let id = width("card", self.cards.iter().map(|card| card.id));
+        let matches = width("matches", self.cards.iter().map(|card| card.matches));
+        let points = width("points", self.cards.iter().map(|card| card.points));
+        let copies = width("copies", self.cards.iter().map(|card| card.copies));
+        writeln!(
+            f,
+            "{:>id$}  {:>matches$}  {:>points$}  {:>copies$}  copied from",
+            "card", "matches", "points", "copies"
+        )?;
+        for card in &self.cards {
+            write!(
+                f,
+                "{:>id$}  {:>matches$}  {:>points$}  {:>copies$}",
+                card.id, card.matches, card.points, card.copies
+            )?;
+            for (index, from) in card.copied_from.iter().enumerate() {
+                write!(f, "{}{from}", if index == 0 { "  " } else { ", " })?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "total: {}", self.total())
+    }
+}
+
+pub fn part_one(input: &str) -> u128 {
+    parse(input).map(|card| card.points()).sum()
+}
+
+pub fn part_two(input: &str) -> usize {
+    let matches = parse(input).map(|card| card.matches()).collect::<Vec<_>>();
+    copies(&matches).iter().sum()
+}
+
+/// Parses the cards line by line, in the order given, whatever their ids;
+/// [`parse_cards`] is the one that checks them.
+fn parse(input: &str) -> impl Iterator<Item = Scratchcard> + '_ {
+    input.lines().map(|line| line.parse().unwrap())
+}
+
+/// How many copies of each card are held in the end, when each copy of card
+/// `index` wins one copy of each of the `matches[index]` cards after it.
+///
+/// The copies a card wins are added to a running bonus for the cards after
+/// it, and taken back off once its run is over, so each card is visited
+/// once however many copies there are.
+fn copies(matches: &[usize]) -> Vec<usize> {
+    let end = matches.len();
+    let mut expiring = vec![0; end + 1];
+    let mut bonus = 0;
+    matches
+        .iter()
+        .enumerate()
+        .map(|(index, &matches)| {
+            bonus -= expiring[index];
+            let copies = 1 + bonus;
+            bonus += copies;
+            expiring[(index + 1 + matches).min(end)] += copies;
+            copies
+        })
+        .collect()
 }
 
-fn parse_numbers(input: &str) -> HashSet<usize> {
-    input
-        .split(' ')
-        .filter(|n| !n.is_empty())
-        .map(|n| n.trim().parse::<usize>().unwrap())
-        .collect::<HashSet<usize>>()
+fn parse_numbers(input: &str) -> Result<u128, Box<dyn Error>> {
+    input.split_whitespace().try_fold(0, |bits, number| {
+        let number = number.parse::<usize>()?;
+        if number > Scratchcard::MAX_NUMBER {
+            return Err(format!("{number} is larger than {}", Scratchcard::MAX_NUMBER).into());
+        }
+        Ok(bits | 1 << number)
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use super::*;
+    use crate::Rng;
 
     #[test]
     fn part_one_example1() {
@@ -110,4 +348,231 @@ Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11
         let result = part_two(input);
         assert_eq!(result, 5037841)
     }
+
+    /// The original propagation, one copy at a time.
+    fn copies_naive(matches: &[usize]) -> Vec<usize> {
+        let copies = vec![1; matches.len()];
+        matches
+            .iter()
+            .enumerate()
+            .fold(copies, |mut acc, (index, &matches)| {
+                let times = acc[index];
+                for _ in 0..times {
+                    acc.iter_mut()
+                        .skip(index + 1)
+                        .take(matches)
+                        .for_each(|copies| *copies += 1);
+                }
+                acc
+            })
+    }
+
+    #[test]
+    fn should_agree_with_naive_propagation() {
+        let input = include_str!("../resources/day4_part1");
+        let matches = parse_cards(input, Overflow::Reject)
+            .unwrap()
+            .iter()
+            .map(Scratchcard::matches)
+            .collect::<Vec<_>>();
+        assert_eq!(copies(&matches), copies_naive(&matches));
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..500 {
+            let len = rng.next(16);
+            let matches = (0..len).map(|_| rng.next(6)).collect::<Vec<_>>();
+            assert_eq!(copies(&matches), copies_naive(&matches), "{matches:?}");
+        }
+    }
+
+    #[test]
+    fn should_propagate_large_copy_counts() {
+        let matches = vec![1; 100];
+        assert_eq!(copies(&matches).iter().sum::<usize>(), 100 * 101 / 2);
+        // Each card holds one more than the two before it together, a
+        // Fibonacci number less one, and they sum to F(64) - 3 - 60.
+        let fibonacci = (0..64).fold((0usize, 1usize), |(a, b), _| (b, a + b)).0;
+        assert_eq!(copies(&[2; 60]).iter().sum::<usize>(), fibonacci - 63);
+    }
+
+    /// The original representation, both sides of a card as hash sets.
+    fn hash_sets(line: &str) -> (HashSet<usize>, HashSet<usize>) {
+        let (_, numbers) = line.split_once(':').unwrap();
+        let (winning, held) = numbers.split_once('|').unwrap();
+        let parse = |numbers: &str| {
+            numbers
+                .split_whitespace()
+                .map(|number| number.parse().unwrap())
+                .collect::<HashSet<usize>>()
+        };
+        (parse(winning), parse(held))
+    }
+
+    #[test]
+    fn should_match_like_hash_sets() {
+        let input = include_str!("../resources/day4_part1");
+        for line in input.lines() {
+            let card = line.parse::<Scratchcard>().unwrap();
+            let (winning, held) = hash_sets(line);
+            let mut matching = winning.intersection(&held).copied().collect::<Vec<_>>();
+            matching.sort_unstable();
+            assert_eq!(card.matching().collect::<Vec<_>>(), matching);
+            assert_eq!(card.matches(), matching.len());
+            assert_eq!(card.winning().count(), winning.len());
+            assert_eq!(card.held().count(), held.len());
+        }
+    }
+
+    #[test]
+    fn should_reject_malformed_cards() {
+        let error = |card: &str| card.parse::<Scratchcard>().unwrap_err().to_string();
+        assert_eq!(error("Card 1: 1 2 3"), "missing '|'");
+        assert_eq!(error("1 2 | 3"), "missing ':'");
+        assert_eq!(error("Card: 1 | 3"), "invalid card id \"Card\"");
+        assert_eq!(error("Game 1: 1 | 3"), "invalid card id \"Game 1\"");
+        assert_eq!(error("Card 1: 1 128 | 3"), "128 is larger than 127");
+        assert_eq!(error("Card 1: 1 x | 3"), "invalid digit found in string");
+        let card = "Card  12: 0 127 | 127 0".parse::<Scratchcard>().unwrap();
+        assert_eq!(card.id(), 12);
+        assert_eq!(card.matching().collect::<Vec<_>>(), [0, 127]);
+    }
+
+    /// Compares matching cards as hash sets and as bitsets, run with
+    /// `cargo test --release -p aoc2023 day4 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_matching() {
+        use std::{hint::black_box, time::Instant};
+        const ITERATIONS: u32 = 1000;
+        let input = include_str!("../resources/day4_part1");
+        let sets = input.lines().map(hash_sets).collect::<Vec<_>>();
+        let cards = parse_cards(input, Overflow::Reject).unwrap();
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let matches = black_box(&sets)
+                .iter()
+                .map(|(winning, held)| winning.intersection(held).count())
+                .sum::<usize>();
+            black_box(matches);
+        }
+        let by_hash_set = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let matches = black_box(&cards)
+                .iter()
+                .map(Scratchcard::matches)
+                .sum::<usize>();
+            black_box(matches);
+        }
+        let by_bitset = start.elapsed() / ITERATIONS;
+
+        println!("day4 matching with hash sets: {by_hash_set:?}, with bitsets: {by_bitset:?}");
+    }
+
+    #[test]
+    fn should_score_cards_matching_more_numbers_than_a_usize_has_bits() {
+        let card = |matches: usize| {
+            let numbers = (0..matches)
+                .map(|number| number.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("Card 1: {numbers} | {numbers}")
+        };
+        let scratchcard = card(65).parse::<Scratchcard>().unwrap();
+        assert_eq!(scratchcard.matches(), 65);
+        assert_eq!(scratchcard.points(), 1 << 64);
+        assert_eq!(part_one(&card(Scratchcard::MAX_NUMBER + 1)), 1 << 127);
+    }
+
+    #[test]
+    fn parts_should_not_validate_card_ids() {
+        assert_eq!(part_one("Card 3: 1 | 1"), 1);
+        assert_eq!(part_two("Card 3: 1 | 1"), 1);
+        let input = "Card 2: 1 2 | 1 2\nCard 2: 3 | 3\nCard 1: 4 | 5";
+        assert_eq!(part_one(input), 3);
+        assert_eq!(part_two(input), 7);
+        assert!(parse_cards(input, Overflow::Truncate).is_err());
+    }
+
+    #[test]
+    fn should_validate_card_ids() {
+        let error = |input| {
+            parse_cards(input, Overflow::Truncate)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("Card 1: 1 | 2\nCard 3: 1 | 2"),
+            "line 2: expected card 2, found card 3"
+        );
+        assert_eq!(
+            error("Card 2: 1 | 2\nCard 1: 1 | 2"),
+            "line 1: expected card 1, found card 2"
+        );
+        assert_eq!(
+            error("Card 1: 1 | 2\nCard 1: 1 | 2"),
+            "line 2: card 1 is already on line 1"
+        );
+        assert_eq!(error("Card 1: 1 | 2\nCard 2 1 | 2"), "line 2: missing ':'");
+    }
+
+    #[test]
+    fn should_define_copies_past_the_end() {
+        let input = "Card 1: 1 2 | 1 2\nCard 2: 3 4 5 | 3 4 5\nCard 3: 6 | 7";
+        assert_eq!(
+            total_scratchcards(input, Overflow::Truncate).unwrap(),
+            1 + 2 + 4
+        );
+        assert_eq!(
+            total_scratchcards(input, Overflow::Reject)
+                .unwrap_err()
+                .to_string(),
+            "line 2: card 2 would copy cards past card 3"
+        );
+        let input = "Card 1: 1 2 | 1 2\nCard 2: 3 | 3\nCard 3: 6 | 7";
+        assert_eq!(
+            total_scratchcards(input, Overflow::Reject).unwrap(),
+            1 + 2 + 4
+        );
+    }
+
+    #[test]
+    fn should_report_each_card() {
+        let input = r#"Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
+Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
+Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
+Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
+Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11
+"#;
+        let report = report(input, Overflow::Reject).unwrap();
+        assert_eq!(
+            report.to_string(),
+            "\
+card  matches  points  copies  copied from
+   1        4       8       1
+   2        2       2       2  1
+   3        2       2       4  1, 2
+   4        1       1       8  1, 2, 3
+   5        0       0      14  1, 3, 4
+   6        0       0       1
+total: 30"
+        );
+    }
+
+    #[test]
+    fn should_report_copies_won_from_earlier_cards() {
+        let input = include_str!("../resources/day4_part1");
+        let report = report(input, Overflow::Reject).unwrap();
+        for card in &report.cards {
+            let won = card
+                .copied_from
+                .iter()
+                .map(|&id| report.cards[id - 1].copies)
+                .sum::<usize>();
+            assert_eq!(card.copies, 1 + won);
+        }
+        assert_eq!(report.total(), part_two(input));
+    }
 }
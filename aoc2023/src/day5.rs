@@ -48,7 +48,7 @@ pub fn part_two(input: &str) -> usize {
         .chunks(2)
         .map(|chunk| (chunk[0], chunk[1]))
         .par_bridge()
-        .flat_map(|(start, len)| (start..start + len))
+        .flat_map(|(start, len)| start..start + len)
         .fold(|| usize::MAX, fold_seeds(&steps))
         .min()
         .unwrap_or(0)
@@ -1,41 +1,89 @@
-use std::{
-    cmp::{Ordering, Reverse},
-    collections::HashMap,
-};
+use std::{cmp::Ordering, collections::HashMap, error::Error, fmt::Display};
 
 pub fn part_one(input: &str) -> usize {
-    compute(input, Card::Card)
+    total_winnings(input, &Standard)
 }
 
 pub fn part_two(input: &str) -> usize {
-    compute(input, Card::Wildcard)
+    total_winnings(input, &Jokers)
 }
 
-fn compute(input: &str, char_to_card: impl Fn(char) -> Card) -> usize {
-    let mut hands = input
-        .lines()
-        .map(|line| parse_line(line, &char_to_card))
-        .collect::<Vec<_>>();
-    hands.sort_by(|(left, _), (right, _)| left.cmp(right));
-    hands
+/// Ranks every hand in `input` under `rules` and sums each bid multiplied by
+/// the rank of its hand. Every hand must have the same number of cards.
+pub fn total_winnings(input: &str, rules: &impl RuleSet) -> usize {
+    try_total_winnings(input, rules, HandSizes::Uniform).unwrap()
+}
+
+/// Whether the hands in an input may have different numbers of cards.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HandSizes {
+    /// Every hand must have as many cards as the first one.
+    Uniform,
+    /// Hands may have any number of cards up to [`MAX_HAND_SIZE`].
+    Mixed,
+}
+
+/// The largest number of cards in a hand.
+pub const MAX_HAND_SIZE: usize = 15;
+
+/// Like [`total_winnings`], but fails on malformed lines, on cards that are
+/// not in the [`RuleSet::order`] of `rules`, on hands with no cards or more
+/// than [`MAX_HAND_SIZE`] cards, and on hands of different sizes unless
+/// `sizes` is [`HandSizes::Mixed`].
+pub fn try_total_winnings(
+    input: &str,
+    rules: &impl RuleSet,
+    sizes: HandSizes,
+) -> Result<usize, Box<dyn Error>> {
+    let mut expected_size = None;
+    let mut hands = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let (hand, bid) =
+            parse_line(line).map_err(|error| format!("line {}: {error}", number + 1))?;
+        check_cards(&hand.0, rules).map_err(|error| format!("line {}: {error}", number + 1))?;
+        let size = hand.0.len();
+        if size == 0 || size > MAX_HAND_SIZE {
+            return Err(format!(
+                "line {}: hand has {size} cards, expected 1 to {MAX_HAND_SIZE}",
+                number + 1
+            )
+            .into());
+        }
+        let expected = *expected_size.get_or_insert(size);
+        if sizes == HandSizes::Uniform && size != expected {
+            return Err(format!(
+                "line {}: hand has {size} cards, expected {expected}",
+                number + 1
+            )
+            .into());
+        }
+        hands.push((hand.sort_key(rules), bid));
+    }
+    hands.sort_unstable_by_key(|&(key, _)| key);
+    Ok(hands
         .iter()
         .enumerate()
         .map(|(index, (_, rank))| (index + 1) * rank)
-        .sum()
+        .sum())
 }
 
-fn parse_line(line: &str, char_to_card: impl Fn(char) -> Card) -> (Hand, usize) {
+fn parse_line(line: &str) -> Result<(Hand, usize), Box<dyn Error>> {
     let mut columns = line.split_whitespace();
-    let cards = columns
-        .next()
-        .unwrap()
-        .chars()
-        .fold(Vec::new(), |mut acc, ch| {
-            acc.push(char_to_card(ch));
-            acc
-        });
-    let bid = columns.next().unwrap().parse::<usize>().unwrap();
-    (Hand(cards), bid)
+    let cards = columns.next().ok_or("missing hand")?.chars().collect();
+    let bid = columns.next().ok_or("missing bid")?;
+    let bid = bid
+        .parse::<usize>()
+        .map_err(|_| format!("invalid bid {bid:?}"))?;
+    Ok((Hand(cards), bid))
+}
+
+/// Fails on the first card of `cards` that is not in the [`RuleSet::order`]
+/// of `rules`.
+fn check_cards(cards: &[char], rules: &impl RuleSet) -> Result<(), String> {
+    match cards.iter().find(|card| !rules.order().contains(card)) {
+        Some(card) => Err(format!("unknown card {card:?}")),
+        None => Ok(()),
+    }
 }
 
 const BASIC_ORDER: [char; 13] = [
@@ -46,96 +94,330 @@ const WILDCARD_ORDER: [char; 13] = [
     'J', '2', '3', '4', '5', '6', '7', '8', '9', 'T', 'Q', 'K', 'A',
 ];
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
-enum Card {
-    Card(char),
-    Wildcard(char),
+/// The rules of a Camel Cards variant: how cards are ordered, which cards are
+/// wild and how a hand is categorised.
+pub trait RuleSet {
+    /// Every card, from weakest to strongest. At most [`MAX_CARDS`] cards are
+    /// supported.
+    fn order(&self) -> &[char];
+
+    /// Whether `card` can stand in for any other card when categorising a
+    /// hand. No card is wild by default.
+    fn is_wild(&self, _card: char) -> bool {
+        false
+    }
+
+    /// Position of `card` in [`RuleSet::order`], `0` being the weakest.
+    fn rank(&self, card: char) -> u32 {
+        self.order().iter().position(|&c| c == card).unwrap() as u32
+    }
+
+    /// The card every wild card in `cards` stands in for, or `None` if there
+    /// are no wild cards. By default wild cards all join the largest group of
+    /// non-wild cards, the strongest such group on a tie, which always gives
+    /// the strongest category. A hand of only wild cards becomes the strongest
+    /// non-wild card.
+    fn substitution(&self, cards: &[char]) -> Option<char> {
+        if !cards.iter().any(|&card| self.is_wild(card)) {
+            return None;
+        }
+        count_cards(cards.iter().copied().filter(|&card| !self.is_wild(card)))
+            .into_iter()
+            .max_by_key(|&(card, count)| (count, self.rank(card)))
+            .map(|(card, _)| card)
+            .or_else(|| {
+                self.order()
+                    .iter()
+                    .rev()
+                    .find(|&&card| !self.is_wild(card))
+                    .copied()
+            })
+    }
+
+    /// The number of copies of each card in `cards`, largest first, once
+    /// every wild card has been replaced by its [`RuleSet::substitution`].
+    /// Hands are ranked by comparing their shapes element by element, which
+    /// for five cards gives the same order as their [`HandCategory`].
+    fn shape(&self, cards: &[char]) -> Vec<usize> {
+        let substitution = self.substitution(cards);
+        let substituted = cards.iter().map(|&card| match substitution {
+            Some(substitute) if self.is_wild(card) => substitute,
+            _ => card,
+        });
+        shape_of(substituted)
+    }
 }
 
-impl Ord for Card {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let (&ch, &other_ch, order) = match (self, other) {
-            (Card::Card(ch), Card::Card(other_ch)) => (ch, other_ch, BASIC_ORDER),
-            (Card::Wildcard(ch), Card::Wildcard(other_ch)) => (ch, other_ch, WILDCARD_ORDER),
-            _ => unreachable!(),
-        };
-        let this_pos = order.iter().position(|&c| c == ch).unwrap();
-        let that_pos = order.iter().position(|&c| c == other_ch).unwrap();
-        this_pos.cmp(&that_pos)
+/// The rules of part one, no card is wild.
+pub struct Standard;
+
+impl RuleSet for Standard {
+    fn order(&self) -> &[char] {
+        &BASIC_ORDER
     }
 }
 
-impl PartialOrd for Card {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+/// The rules of part two, `J` is a joker and the weakest card.
+pub struct Jokers;
+
+impl RuleSet for Jokers {
+    fn order(&self) -> &[char] {
+        &WILDCARD_ORDER
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        card == 'J'
     }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
-struct Hand(Vec<Card>);
-
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let this_rank = apply_rules(self);
-        let other_rank = apply_rules(other);
-        match this_rank.cmp(&other_rank) {
-            Ordering::Equal => self
-                .0
-                .iter()
-                .zip(&other.0)
-                .find(|(left, right)| left != right)
-                .map(|(left, right)| left.cmp(right))
-                .unwrap(),
-            ordering => ordering,
+/// The largest number of different cards a [`RuleSet`] can order.
+pub const MAX_CARDS: usize = 1 << RANK_BITS;
+
+/// Rules with a custom card order and any number of wild cards.
+///
+/// ```
+/// use aoc2023::day7::{total_winnings, CustomRules};
+///
+/// let twos_wild = CustomRules::new("23456789TJQKA").unwrap().with_wildcards("2");
+/// assert_eq!(total_winnings("22345 10\n33456 1", &twos_wild), 21);
+/// ```
+pub struct CustomRules {
+    order: Vec<char>,
+    wildcards: Vec<char>,
+}
+
+impl CustomRules {
+    /// Creates rules where cards are ordered as in `order`, weakest first.
+    /// Fails if `order` has more than [`MAX_CARDS`] cards or repeats a card.
+    pub fn new(order: &str) -> Result<Self, Box<dyn Error>> {
+        let order = order.chars().collect::<Vec<_>>();
+        if order.len() > MAX_CARDS {
+            return Err(format!(
+                "order has {} cards, at most {MAX_CARDS} are supported",
+                order.len()
+            )
+            .into());
+        }
+        if let Some(card) = order
+            .iter()
+            .enumerate()
+            .find_map(|(index, card)| order[..index].contains(card).then_some(card))
+        {
+            return Err(format!("card {card:?} appears twice in the order").into());
+        }
+        Ok(Self {
+            order,
+            wildcards: Vec::new(),
+        })
+    }
+
+    /// Makes every card in `wildcards` wild.
+    pub fn with_wildcards(mut self, wildcards: &str) -> Self {
+        self.wildcards = wildcards.chars().collect();
+        self
+    }
+}
+
+impl RuleSet for CustomRules {
+    fn order(&self) -> &[char] {
+        &self.order
+    }
+
+    fn is_wild(&self, card: char) -> bool {
+        self.wildcards.contains(&card)
+    }
+}
+
+/// The category of a hand, from weakest to strongest.
+///
+/// Hands of more than five cards get the strongest category their shape
+/// contains, so five or more of a kind is [`HandCategory::FiveOfAKind`] and
+/// two threes of a kind is a [`HandCategory::FullHouse`].
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum HandCategory {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+}
+
+impl HandCategory {
+    /// Categorises a hand from its [`RuleSet::shape`].
+    pub fn from_shape(shape: &[usize]) -> Self {
+        match shape {
+            [(5..), ..] => HandCategory::FiveOfAKind,
+            [4, ..] => HandCategory::FourOfAKind,
+            [3, (2..), ..] => HandCategory::FullHouse,
+            [3, ..] => HandCategory::ThreeOfAKind,
+            [2, 2, ..] => HandCategory::TwoPair,
+            [2, ..] => HandCategory::OnePair,
+            _ => HandCategory::HighCard,
         }
     }
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl Display for HandCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HandCategory::HighCard => "high card",
+            HandCategory::OnePair => "one pair",
+            HandCategory::TwoPair => "two pair",
+            HandCategory::ThreeOfAKind => "three of a kind",
+            HandCategory::FullHouse => "full house",
+            HandCategory::FourOfAKind => "four of a kind",
+            HandCategory::FiveOfAKind => "five of a kind",
+        };
+        write!(f, "{name}")
     }
 }
 
-const J: Card = Card::Wildcard('J');
+/// How a single hand was categorised.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Explanation {
+    pub category: HandCategory,
+    pub shape: Vec<usize>,
+    /// The card every wild card stood in for, `None` if the hand has no wild
+    /// cards.
+    pub substitution: Option<char>,
+}
 
-const FIVE_OF_A_KIND: usize = 6;
-const FOUR_OF_A_KIND: usize = 5;
-const FULL_HOUSE: usize = 4;
-const THREE_OF_A_KIND: usize = 3;
-const TWO_PAIR: usize = 2;
-const ONE_PAIR: usize = 1;
-const HIGH_CARD: usize = 0;
+/// What decided the order between two hands.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Tiebreak {
+    /// One hand has the stronger category.
+    Category,
+    /// The hands have the same category but different shapes, which only
+    /// happens when the hands have different sizes or more than five cards,
+    /// and the first count that differs decided, so three pairs beat two
+    /// pairs.
+    Shape,
+    /// The hands have the same category and the first card that differs,
+    /// at `position`, decided.
+    Card {
+        position: usize,
+        left: char,
+        right: char,
+    },
+    /// The hands have the same category and the same cards.
+    Equal,
+}
 
-fn apply_rules(hand: &Hand) -> usize {
-    let mut mapped = map_cards(&hand.0);
-    mapped.sort_by_key(|hand| Reverse(hand.1));
-    mapped
-        .iter()
-        .find(|(&card, _)| card == J)
-        .map(|_| match mapped.as_slice() {
-            [(_, _)] | [(_, _), (_, _)] => FIVE_OF_A_KIND,
-            [(_, 3), ..] => FOUR_OF_A_KIND,
-            [(_, 2), (_, 2), (&z, 1)] if z != J => FOUR_OF_A_KIND,
-            [(_, 2), (_, 2), (_, 1)] => FULL_HOUSE,
-            [(_, 2), (_, 1), (_, 1), (_, 1)] => THREE_OF_A_KIND,
-            [(_, 1), (_, 1), (_, 1), (_, 1), (_, 1)] => ONE_PAIR,
-            _ => HIGH_CARD,
-        })
-        .unwrap_or(match mapped.as_slice() {
-            [(_, _)] => FIVE_OF_A_KIND,
-            [(_, 4), (_, _)] => FOUR_OF_A_KIND,
-            [(_, 3), (_, 2)] => FULL_HOUSE,
-            [(_, 3), (_, 1), (_, 1)] => THREE_OF_A_KIND,
-            [(_, 2), (_, 2), ..] => TWO_PAIR,
-            [(_, 2), ..] => ONE_PAIR,
-            _ => HIGH_CARD,
-        })
+/// How two hands were categorised and why one is ranked above the other.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Comparison {
+    pub left: Explanation,
+    pub right: Explanation,
+    pub ordering: Ordering,
+    pub tiebreak: Tiebreak,
+}
+
+/// Explains how `hand` is categorised under `rules`. Fails on cards that are
+/// not in the [`RuleSet::order`] of `rules`.
+///
+/// ```
+/// use aoc2023::day7::{explain, HandCategory, Jokers};
+///
+/// let explanation = explain("KTJJT", &Jokers).unwrap();
+/// assert_eq!(explanation.category, HandCategory::FourOfAKind);
+/// assert_eq!(explanation.substitution, Some('T'));
+/// assert_eq!(explain("KTJJX", &Jokers).unwrap_err().to_string(), "unknown card 'X'");
+/// ```
+pub fn explain(hand: &str, rules: &impl RuleSet) -> Result<Explanation, Box<dyn Error>> {
+    let cards = hand.chars().collect::<Vec<_>>();
+    check_cards(&cards, rules)?;
+    let shape = rules.shape(&cards);
+    Ok(Explanation {
+        category: HandCategory::from_shape(&shape),
+        shape,
+        substitution: rules.substitution(&cards),
+    })
+}
+
+/// Explains how `left` and `right` are categorised under `rules`, and what
+/// decides their order. Fails on cards that are not in the
+/// [`RuleSet::order`] of `rules`.
+pub fn explain_against(
+    left: &str,
+    right: &str,
+    rules: &impl RuleSet,
+) -> Result<Comparison, Box<dyn Error>> {
+    let (left_explanation, right_explanation) = (explain(left, rules)?, explain(right, rules)?);
+    let tiebreak = if left_explanation.category != right_explanation.category {
+        Tiebreak::Category
+    } else if left_explanation.shape != right_explanation.shape {
+        Tiebreak::Shape
+    } else {
+        left.chars()
+            .zip(right.chars())
+            .enumerate()
+            .find(|(_, (left, right))| left != right)
+            .map(|(position, (left, right))| Tiebreak::Card {
+                position,
+                left,
+                right,
+            })
+            .unwrap_or(Tiebreak::Equal)
+    };
+    let ordering = match tiebreak {
+        Tiebreak::Category | Tiebreak::Shape => {
+            left_explanation.shape.cmp(&right_explanation.shape)
+        }
+        Tiebreak::Card { left, right, .. } => rules.rank(left).cmp(&rules.rank(right)),
+        Tiebreak::Equal => Ordering::Equal,
+    };
+    Ok(Comparison {
+        left: left_explanation,
+        right: right_explanation,
+        ordering,
+        tiebreak,
+    })
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+struct Hand(Vec<char>);
+
+/// Bits used per count and per card in a [`Hand::sort_key`], so up to 15
+/// cards per hand and 16 card ranks fit.
+const RANK_BITS: u32 = 4;
+
+impl Hand {
+    /// Packs the hand shape and the rank of every card into a single integer,
+    /// so that comparing two keys gives the same result as comparing the
+    /// hands. The shape takes the most significant bits, followed by each card
+    /// in order, both padded with zeros to [`MAX_HAND_SIZE`]. The counts in a
+    /// shape add up to the number of cards, so hands of different sizes always
+    /// differ by shape before their cards are compared.
+    fn sort_key(&self, rules: &impl RuleSet) -> u128 {
+        let pack = |key: u128, value: usize| (key << RANK_BITS) | value as u128;
+        let shape = rules.shape(&self.0);
+        let key = (0..MAX_HAND_SIZE)
+            .map(|index| shape.get(index).copied().unwrap_or(0))
+            .fold(0, pack);
+        (0..MAX_HAND_SIZE)
+            .map(|index| {
+                self.0
+                    .get(index)
+                    .map_or(0, |&card| rules.rank(card) as usize)
+            })
+            .fold(key, pack)
+    }
+}
+
+fn shape_of(cards: impl IntoIterator<Item = char>) -> Vec<usize> {
+    let mut counts = count_cards(cards)
+        .into_iter()
+        .map(|(_, count)| count)
+        .collect::<Vec<_>>();
+    counts.sort_unstable_by(|left, right| right.cmp(left));
+    counts
 }
 
-fn map_cards(cards: &[Card]) -> Vec<(&Card, usize)> {
+fn count_cards(cards: impl IntoIterator<Item = char>) -> Vec<(char, usize)> {
     cards
-        .iter()
+        .into_iter()
         .fold(HashMap::new(), |mut acc, card| {
             acc.entry(card).and_modify(|count| *count += 1).or_insert(1);
             acc
@@ -148,6 +430,95 @@ fn map_cards(cards: &[Card]) -> Vec<(&Card, usize)> {
 mod tests {
     use super::*;
 
+    fn hand(cards: &str) -> Hand {
+        Hand(cards.chars().collect())
+    }
+
+    fn apply_rules(hand: &Hand, rules: &impl RuleSet) -> HandCategory {
+        HandCategory::from_shape(&rules.shape(&hand.0))
+    }
+
+    fn compare(left: &Hand, right: &Hand, rules: &impl RuleSet) -> Ordering {
+        left.sort_key(rules).cmp(&right.sort_key(rules))
+    }
+
+    /// Finds the best shape of `cards` by trying every non-wild card in
+    /// place of every wild card, independently of each other. The shape only
+    /// depends on which cards are in a hand, so results are memoised in
+    /// `seen` by sorted hand, including the partly substituted hands of the
+    /// search.
+    fn oracle_shape(
+        cards: &[char],
+        rules: &impl RuleSet,
+        seen: &mut HashMap<Vec<char>, Vec<usize>>,
+    ) -> Vec<usize> {
+        let mut sorted = cards.to_vec();
+        sorted.sort_unstable();
+        if let Some(shape) = seen.get(&sorted) {
+            return shape.clone();
+        }
+        let shape = match cards.iter().position(|&card| rules.is_wild(card)) {
+            None => shape_of(cards.iter().copied()),
+            Some(position) => rules
+                .order()
+                .iter()
+                .filter(|&&card| !rules.is_wild(card))
+                .map(|&substitute| {
+                    let mut substituted = cards.to_vec();
+                    substituted[position] = substitute;
+                    oracle_shape(&substituted, rules, seen)
+                })
+                .max()
+                .unwrap(),
+        };
+        seen.insert(sorted, shape.clone());
+        shape
+    }
+
+    /// Every hand of `size` cards drawn from `order`, with repetition.
+    fn all_hands(order: &[char], size: u32) -> impl Iterator<Item = Vec<char>> + '_ {
+        (0..order.len().pow(size)).map(move |mut index| {
+            (0..size)
+                .map(|_| {
+                    let card = order[index % order.len()];
+                    index /= order.len();
+                    card
+                })
+                .collect()
+        })
+    }
+
+    /// Checks the shape of every hand of `size` cards, in every order,
+    /// against [`oracle_shape`].
+    fn assert_agrees_with_oracle(rules: &impl RuleSet, size: u32) {
+        let mut seen = HashMap::new();
+        for cards in all_hands(rules.order(), size) {
+            assert_eq!(
+                rules.shape(&cards),
+                oracle_shape(&cards, rules, &mut seen),
+                "{}",
+                cards.iter().collect::<String>()
+            );
+        }
+    }
+
+    /// Every ordering of `cards`.
+    fn permutations(cards: &[char]) -> Vec<Vec<char>> {
+        if cards.is_empty() {
+            return vec![Vec::new()];
+        }
+        (0..cards.len())
+            .flat_map(|index| {
+                let mut rest = cards.to_vec();
+                let card = rest.remove(index);
+                permutations(&rest).into_iter().map(move |mut permutation| {
+                    permutation.push(card);
+                    permutation
+                })
+            })
+            .collect()
+    }
+
     #[test]
     fn part_one_example1() {
         let input = r#"32T3K 765
@@ -186,95 +557,348 @@ QQQJA 483"#;
 
     #[test]
     fn test_ordering() {
-        let k = Card::Card('K');
-        let j = Card::Card('J');
-        assert_eq!(k.partial_cmp(&j), Some(Ordering::Greater))
+        assert_eq!(
+            Standard.rank('K').cmp(&Standard.rank('J')),
+            Ordering::Greater
+        )
     }
 
     #[test]
     fn should_say_hand_is_greater() {
-        let left = Hand(vec![
-            Card::Card('Q'),
-            Card::Card('Q'),
-            Card::Card('Q'),
-            Card::Card('J'),
-            Card::Card('A'),
-        ]);
-        let right = Hand(vec![
-            Card::Card('T'),
-            Card::Card('5'),
-            Card::Card('5'),
-            Card::Card('J'),
-            Card::Card('5'),
-        ]);
-        assert_eq!(left.cmp(&right), Ordering::Greater)
+        let left = hand("QQQJA");
+        let right = hand("T55J5");
+        assert_eq!(compare(&left, &right, &Standard), Ordering::Greater)
     }
 
     #[test]
     fn should_say_hand_is_less() {
-        let left = Hand(vec![
-            Card::Card('K'),
-            Card::Card('T'),
-            Card::Card('J'),
-            Card::Card('J'),
-            Card::Card('T'),
-        ]);
-        let right = Hand(vec![
-            Card::Card('T'),
-            Card::Card('5'),
-            Card::Card('5'),
-            Card::Card('J'),
-            Card::Card('5'),
-        ]);
-        assert_eq!(left.cmp(&right), Ordering::Less)
+        let left = hand("KTJJT");
+        let right = hand("T55J5");
+        assert_eq!(compare(&left, &right, &Standard), Ordering::Less)
     }
 
     #[test]
     fn should_say_card_is_greater() {
-        let left = Card::Card('A');
-        let right = Card::Card('2');
-        assert_eq!(left.cmp(&right), Ordering::Greater);
+        assert_eq!(
+            Standard.rank('A').cmp(&Standard.rank('2')),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn sort_key_should_order_category_before_cards() {
+        let full_house = hand("22333");
+        let three_of_a_kind = hand("AAA2K");
+        assert!(full_house.sort_key(&Standard) > three_of_a_kind.sort_key(&Standard));
+    }
+
+    #[test]
+    fn sort_key_should_break_ties_on_first_differing_card() {
+        let left = hand("KK677");
+        let right = hand("KTJJT");
+        assert!(left.sort_key(&Standard) > right.sort_key(&Standard));
+    }
+
+    /// Compares sorting by comparing hands, which evaluates both hands on
+    /// every comparison, against sorting by precomputed keys. Run with
+    /// `cargo test --release -p aoc2023 day7 -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_sort_key() {
+        use std::{hint::black_box, time::Instant};
+        const ITERATIONS: u32 = 100;
+        let input = include_str!("../resources/day7_part1");
+        let hands = input
+            .lines()
+            .map(|line| parse_line(line).unwrap().0)
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let mut hands = hands.clone();
+            hands.sort_by(|left, right| compare(left, right, &Standard));
+            black_box(hands);
+        }
+        let by_comparison = start.elapsed() / ITERATIONS;
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let mut keys = hands
+                .iter()
+                .map(|hand| hand.sort_key(&Standard))
+                .collect::<Vec<_>>();
+            keys.sort_unstable();
+            black_box(keys);
+        }
+        let by_key = start.elapsed() / ITERATIONS;
+
+        println!("day7 sort by comparison: {by_comparison:?}, by precomputed key: {by_key:?}");
     }
 
     #[test]
     fn should_give_correct_rank_with_wildcard_rules() {
-        let hand = "JJQQQ";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 6);
+        assert_eq!(
+            apply_rules(&hand("JJQQQ"), &Jokers),
+            HandCategory::FiveOfAKind
+        );
     }
 
     #[test]
     fn should_be_full_house_with_wildcard_rules() {
-        let hand = "33QQQ";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 4);
+        assert_eq!(
+            apply_rules(&hand("33QQQ"), &Jokers),
+            HandCategory::FullHouse
+        );
     }
 
     #[test]
     fn should_be_three_of_a_kind_with_wildcard_rules() {
-        let hand = "3KJJQ";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 3);
+        assert_eq!(
+            apply_rules(&hand("3KJJQ"), &Jokers),
+            HandCategory::ThreeOfAKind
+        );
     }
 
     #[test]
     fn should_be_two_pair_with_wildcard_rules() {
-        let hand = "3KQQK";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 2);
+        assert_eq!(apply_rules(&hand("3KQQK"), &Jokers), HandCategory::TwoPair);
     }
 
     #[test]
     fn should_be_one_pair_with_wildcard_rules() {
-        let hand = "J4729";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 1);
+        assert_eq!(apply_rules(&hand("J4729"), &Jokers), HandCategory::OnePair);
     }
 
     #[test]
     fn should_be_high_card_with_wildcard_rules() {
-        let hand = "3KQT1";
-        let hand = Hand(hand.chars().map(Card::Wildcard).collect());
-        assert_eq!(apply_rules(&hand), 0);
+        assert_eq!(apply_rules(&hand("3KQT1"), &Jokers), HandCategory::HighCard);
+    }
+
+    #[test]
+    fn should_be_five_of_a_kind_with_only_jokers() {
+        assert_eq!(
+            apply_rules(&hand("JJJJJ"), &Jokers),
+            HandCategory::FiveOfAKind
+        );
+    }
+
+    #[test]
+    fn should_allow_many_wild_ranks() {
+        let rules = CustomRules::new(&BASIC_ORDER.iter().collect::<String>())
+            .unwrap()
+            .with_wildcards("23");
+        assert_eq!(
+            apply_rules(&hand("23AKA"), &rules),
+            HandCategory::FourOfAKind
+        );
+    }
+
+    #[test]
+    fn should_rank_aces_low() {
+        let aces_low = CustomRules::new("A23456789TJQK").unwrap();
+        let left = hand("A2345");
+        let right = hand("23456");
+        assert_eq!(compare(&left, &right, &aces_low), Ordering::Less);
+        assert_eq!(compare(&left, &right, &Standard), Ordering::Greater);
+    }
+
+    #[test]
+    fn should_explain_joker_substitution() {
+        let explanation = explain("JJJJJ", &Jokers).unwrap();
+        assert_eq!(explanation.category, HandCategory::FiveOfAKind);
+        assert_eq!(explanation.substitution, Some('A'));
+        assert_eq!(explain("QQQJA", &Standard).unwrap().substitution, None);
+    }
+
+    #[test]
+    fn should_explain_tiebreak_on_card() {
+        let comparison = explain_against("T55J5", "KTJJT", &Jokers).unwrap();
+        assert_eq!(comparison.left.category, HandCategory::FourOfAKind);
+        assert_eq!(comparison.right.category, HandCategory::FourOfAKind);
+        assert_eq!(
+            comparison.tiebreak,
+            Tiebreak::Card {
+                position: 0,
+                left: 'T',
+                right: 'K'
+            }
+        );
+        assert_eq!(comparison.ordering, Ordering::Less);
+    }
+
+    #[test]
+    fn should_explain_tiebreak_on_category() {
+        let comparison = explain_against("KK677", "32T3K", &Standard).unwrap();
+        assert_eq!(comparison.tiebreak, Tiebreak::Category);
+        assert_eq!(comparison.ordering, Ordering::Greater);
+        let comparison = explain_against("KK677", "KK677", &Standard).unwrap();
+        assert_eq!(comparison.tiebreak, Tiebreak::Equal);
+        assert_eq!(comparison.ordering, Ordering::Equal);
+    }
+
+    #[test]
+    fn should_reject_unknown_cards_when_explaining() {
+        let error = explain("KTJJX", &Jokers).unwrap_err();
+        assert_eq!(error.to_string(), "unknown card 'X'");
+        let error = explain_against("KTJJT", "KTJJX", &Jokers).unwrap_err();
+        assert_eq!(error.to_string(), "unknown card 'X'");
+        let error = explain_against("KT1JT", "KTJJT", &Standard).unwrap_err();
+        assert_eq!(error.to_string(), "unknown card '1'");
+    }
+
+    #[test]
+    fn should_display_category() {
+        assert_eq!(HandCategory::ThreeOfAKind.to_string(), "three of a kind");
+    }
+
+    #[test]
+    fn shape_should_not_depend_on_card_order() {
+        let rules = CustomRules::new("23456789TJQKA")
+            .unwrap()
+            .with_wildcards("2TA");
+        for hand in [
+            "23456", "AAKKQ", "JJ2JQ", "TTT2A", "KQJT9", "JJJJJ", "2TAK9",
+        ] {
+            let cards = hand.chars().collect::<Vec<_>>();
+            for permutation in permutations(&cards) {
+                assert_eq!(Standard.shape(&permutation), Standard.shape(&cards));
+                assert_eq!(Jokers.shape(&permutation), Jokers.shape(&cards));
+                assert_eq!(rules.shape(&permutation), rules.shape(&cards));
+            }
+        }
+    }
+
+    #[test]
+    fn all_hands_should_agree_with_oracle_without_wildcards() {
+        assert_agrees_with_oracle(&Standard, 5);
+    }
+
+    #[test]
+    fn all_hands_should_agree_with_oracle_with_jokers() {
+        assert_agrees_with_oracle(&Jokers, 5);
+    }
+
+    #[test]
+    fn all_hands_should_agree_with_oracle_with_many_wild_ranks() {
+        assert_agrees_with_oracle(
+            &CustomRules::new("23456789TJQKA")
+                .unwrap()
+                .with_wildcards("2TA"),
+            5,
+        );
+    }
+
+    #[test]
+    fn larger_hands_should_agree_with_oracle() {
+        let rules = CustomRules::new("J234").unwrap().with_wildcards("J");
+        assert_agrees_with_oracle(&rules, 6);
+        assert_agrees_with_oracle(&rules, 7);
+    }
+
+    #[test]
+    fn should_categorise_larger_hands_from_shape() {
+        assert_eq!(
+            apply_rules(&hand("AAAAAAK"), &Standard),
+            HandCategory::FiveOfAKind
+        );
+        assert_eq!(
+            apply_rules(&hand("AAAKKK"), &Standard),
+            HandCategory::FullHouse
+        );
+        assert_eq!(
+            apply_rules(&hand("AAKKQQ"), &Standard),
+            HandCategory::TwoPair
+        );
+        assert_eq!(
+            apply_rules(&hand("AKQT98"), &Standard),
+            HandCategory::HighCard
+        );
+    }
+
+    #[test]
+    fn should_rank_larger_hands_by_shape() {
+        let six_of_a_kind = hand("222222K");
+        let five_of_a_kind = hand("AAAAAKK");
+        assert_eq!(
+            compare(&six_of_a_kind, &five_of_a_kind, &Standard),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn should_rank_longer_hand_above_its_prefix() {
+        let comparison = explain_against("AKQT98", "AKQT9", &Standard).unwrap();
+        assert_eq!(comparison.tiebreak, Tiebreak::Shape);
+        assert_eq!(comparison.ordering, Ordering::Greater);
+        assert_eq!(
+            compare(&hand("AKQT98"), &hand("AKQT9"), &Standard),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn should_reject_mixed_sizes_by_default() {
+        let input = "AAKKQ 1\nAAKKQQ 2";
+        let error = try_total_winnings(input, &Standard, HandSizes::Uniform).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: hand has 6 cards, expected 5");
+        assert_eq!(
+            try_total_winnings(input, &Standard, HandSizes::Mixed).unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn should_reject_oversized_hands() {
+        let input = "2345678923456789 1";
+        let error = try_total_winnings(input, &Standard, HandSizes::Mixed).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 1: hand has 16 cards, expected 1 to 15"
+        );
+    }
+
+    #[test]
+    fn should_reject_malformed_lines() {
+        let error = |input| {
+            try_total_winnings(input, &Standard, HandSizes::Uniform)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("AAKKQ 1\n\nAAKKQ 2"), "line 2: missing hand");
+        assert_eq!(error("AAKKQ"), "line 1: missing bid");
+        assert_eq!(error("AAKKQ 1\nAAKKQ x"), "line 2: invalid bid \"x\"");
+        assert_eq!(error("AAKKX 1"), "line 1: unknown card 'X'");
+    }
+
+    #[test]
+    fn should_reject_orders_that_do_not_fit_a_sort_key() {
+        let error = |order| CustomRules::new(order).err().unwrap().to_string();
+        assert_eq!(
+            error("0123456789ABCDEFG"),
+            "order has 17 cards, at most 16 are supported"
+        );
+        assert_eq!(error("23A456A"), "card 'A' appears twice in the order");
+        let rules = CustomRules::new("0123456789ABCDEF").unwrap();
+        let weak = Hand("0123".chars().collect());
+        let strong = Hand("0124".chars().collect());
+        assert!(weak.sort_key(&rules) < strong.sort_key(&rules));
+        assert!(Hand("FEDC".chars().collect()).sort_key(&rules) > strong.sort_key(&rules));
+    }
+
+    #[test]
+    fn should_explain_shape_tiebreak_within_a_category() {
+        let comparison = explain_against("AAKKQQ", "AAKKQJ", &Standard).unwrap();
+        assert_eq!(comparison.left.category, HandCategory::TwoPair);
+        assert_eq!(comparison.right.category, HandCategory::TwoPair);
+        assert_eq!(comparison.tiebreak, Tiebreak::Shape);
+        assert_eq!(comparison.ordering, Ordering::Greater);
+        let comparison = explain_against("AAAKKQ", "AAKKQQ", &Standard).unwrap();
+        assert_eq!(comparison.tiebreak, Tiebreak::Category);
+        assert_eq!(comparison.ordering, Ordering::Greater);
+        let comparison = explain_against("AK", "AKQ", &Standard).unwrap();
+        assert_eq!(comparison.left.category, HandCategory::HighCard);
+        assert_eq!(comparison.right.category, HandCategory::HighCard);
+        assert_eq!(comparison.tiebreak, Tiebreak::Shape);
+        assert_eq!(comparison.ordering, Ordering::Less);
     }
 }
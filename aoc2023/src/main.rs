@@ -0,0 +1,161 @@
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    process,
+};
+
+use aoc2023::{day1, day2, day3, day4, day5, day6, day7};
+
+const USAGE: &str = "usage: aoc2023 <day> [input] [--trace] [--report [--cubes <n>]] [--visualize]
+
+Runs both parts of a day against `input`, by default the puzzle input in
+the resources directory.
+
+  --trace    day 1: print the first and last number picked on each line
+  --report   day 2: print the minimum bag of each game, the draw making it
+             impossible with the puzzle bag, the share of each colour and
+             the most likely bag with as many cubes as the smallest bag
+             every game is possible with
+             day 4: print the matches, points and copies of each card, and
+             the earlier cards that won those copies
+  --cubes <n>
+             day 2: make the most likely bag of the report hold n cubes
+  --visualize
+             day 3: print the schematic with part numbers, other numbers,
+             symbols and gears coloured, or marked on the line below when
+             NO_COLOR is set or the output is not a terminal";
+
+struct Options {
+    day: u32,
+    input: String,
+    trace: bool,
+    report: bool,
+    cubes: Option<usize>,
+    visualize: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut day = None;
+    let mut input = None;
+    let mut trace = false;
+    let mut report = false;
+    let mut cubes = None;
+    let mut visualize = false;
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace = true,
+            "--report" => report = true,
+            "--cubes" => {
+                let value = args.next().ok_or("missing number of cubes")?;
+                cubes = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid number of cubes {value}"))?,
+                );
+            }
+            "--visualize" => visualize = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {flag}")),
+            _ if day.is_none() => {
+                day = Some(arg.parse().map_err(|_| format!("invalid day {arg}"))?)
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let day = day.ok_or("missing day")?;
+    let input =
+        input.unwrap_or_else(|| format!("{}/resources/day{day}_part1", env!("CARGO_MANIFEST_DIR")));
+    if trace && day != 1 {
+        return Err("--trace is only supported for day 1".into());
+    }
+    if report && day != 2 && day != 4 {
+        return Err("--report is only supported for days 2 and 4".into());
+    }
+    if cubes.is_some() && !(report && day == 2) {
+        return Err("--cubes is only supported with --report for day 2".into());
+    }
+    if visualize && day != 3 {
+        return Err("--visualize is only supported for day 3".into());
+    }
+    Ok(Options {
+        day,
+        input,
+        trace,
+        report,
+        cubes,
+        visualize,
+    })
+}
+
+fn run(options: &Options, input: &str) -> Result<(), String> {
+    match options.day {
+        1 => {
+            println!("part one: {}", day1::part_one(input));
+            println!("part two: {}", day1::part_two(input));
+            if options.trace {
+                for line in day1::Vocabulary::english().trace(input) {
+                    println!("{line}");
+                }
+            }
+        }
+        2 => {
+            println!("part one: {}", day2::part_one(input));
+            println!("part two: {}", day2::part_two(input));
+            if options.report {
+                println!(
+                    "{}",
+                    day2::analyse(input, &day2::Bag::puzzle(), options.cubes)
+                );
+            }
+        }
+        3 => {
+            println!("part one: {}", day3::part_one(input));
+            println!("part two: {}", day3::part_two(input));
+            if options.visualize {
+                let style = if env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal() {
+                    day3::Style::Ansi
+                } else {
+                    day3::Style::Markers
+                };
+                let schematic = day3::Schematic::new(input, &day3::Symbols::default());
+                print!("{}", schematic.render(&day3::GearRules::puzzle(), style));
+            }
+        }
+        4 => {
+            println!("part one: {}", day4::part_one(input));
+            println!("part two: {}", day4::part_two(input));
+            if options.report {
+                let report = day4::report(input, day4::Overflow::Truncate)
+                    .map_err(|error| error.to_string())?;
+                println!("{report}");
+            }
+        }
+        5 => {
+            println!("part one: {}", day5::part_one(input));
+            println!("part two: {}", day5::part_two(input));
+        }
+        6 => println!("parts one and two: {}", day6::part_one_and_two(input)),
+        7 => {
+            println!("part one: {}", day7::part_one(input));
+            println!("part two: {}", day7::part_two(input));
+        }
+        day => return Err(format!("day {day} is not solved")),
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{error}\n\n{USAGE}");
+        process::exit(2);
+    });
+    let input = fs::read_to_string(&options.input).unwrap_or_else(|error| {
+        eprintln!("could not read {}: {error}", options.input);
+        process::exit(1);
+    });
+    if let Err(error) = run(&options, &input) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}